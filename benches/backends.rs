@@ -0,0 +1,61 @@
+//! Times the classification of a fixed set of machines at several radii, with whichever
+//! reachable set backend was compiled in. Compare the backends by running it once with each:
+//!
+//! ```text
+//! cargo bench --bench backends
+//! cargo bench --bench backends --features backend-hash
+//! cargo bench --bench backends --features backend-bitset
+//! ```
+
+use std::time::{Duration, Instant};
+
+use simple_ngram_bb::ngram::{classify_with_config, ClassifyConfig};
+use simple_ngram_bb::program::Program;
+
+const MACHINES: [&str; 8] = [
+    "1RB1LC_1RC1RB_1RD0LE_1LA1LD_---0LA",
+    "1RB1RA_1LC1LB_1RA1LD_1RA1LE_---0LC",
+    "1RB1LB_1LA0LC_---1LD_1RD0RA",
+    "1RB1LB_1LA0RC_---1LD_1RD0LA_------",
+    "1RB0LC_0LA1RD_1LA0RB_1LE---_0RA1RE",
+    "1RB1LC_1RC1RB_1RD0LE_1LA1LD_0LE0LA",
+    "0RE0LA_1RD0LD_1LA1RC_1RD0LD_1RC1RE",
+    "1RB0RD_1LC1LB_1RA0LB_0RE1RD_---1RA",
+];
+
+const RADII: [u8; 4] = [2, 4, 8, 12];
+
+const RUNS: usize = 3;
+
+const MAX_CONTEXT_COUNT: usize = 200_000;
+
+fn backend() -> &'static str {
+    if cfg!(feature = "backend-hash") {
+        "hash"
+    } else if cfg!(feature = "backend-bitset") {
+        "bitset"
+    } else {
+        "btree"
+    }
+}
+
+fn main() {
+    let programs: Vec<Program> = MACHINES.iter().map(|m| Program::from_string(m)).collect();
+
+    println!("backend: {}", backend());
+    println!("{:<8} {:>8} {:>12}", "radius", "loops", "best time");
+    for radius in RADII {
+        let config = ClassifyConfig::new(radius).with_max_context_count(MAX_CONTEXT_COUNT);
+        let mut best = Duration::MAX;
+        let mut loops = 0;
+        for _ in 0..RUNS {
+            let start = Instant::now();
+            loops = programs
+                .iter()
+                .filter(|program| classify_with_config(program, &config).is_ok())
+                .count();
+            best = best.min(start.elapsed());
+        }
+        println!("{radius:<8} {loops:>8} {:>12.2?}", best);
+    }
+}
@@ -0,0 +1,38 @@
+//! Classifies a few well-known machines at several radii and prints a table of the decisions.
+//!
+//! Run with `cargo run --release --example classify_champions`.
+
+use simple_ngram_bb::ngram::{classify_multi, ClassifyConfig};
+use simple_ngram_bb::program::Program;
+
+const MACHINES: [(&str, &str); 5] = [
+    ("BB5 champion (halts)", "1RB1LC_1RC1RB_1RD0LE_1LA1LD_---0LA"),
+    (
+        "BB5 runner-up (halts)",
+        "1RB1RA_1LC1LB_1RA1LD_1RA1LE_---0LC",
+    ),
+    ("BB4 champion (halts)", "1RB1LB_1LA0LC_---1LD_1RD0RA"),
+    ("looper", "1RB1LB_1LA0RC_---1LD_1RD0LA_------"),
+    ("looper", "1RB0LC_0LA1RD_1LA0RB_1LE---_0RA1RE"),
+];
+
+const RADII: [u8; 4] = [1, 2, 4, 8];
+
+fn main() {
+    let config = ClassifyConfig::default();
+
+    print!("{:<24}{:<38}", "name", "machine");
+    for radius in RADII {
+        print!("{:>8}", format!("r={}", radius));
+    }
+    println!();
+
+    for (name, machine) in MACHINES {
+        let program = Program::from_string(machine);
+        print!("{:<24}{:<38}", name, machine);
+        for outcome in classify_multi(&program, &RADII, &config) {
+            print!("{:>8}", if outcome.is_ok() { "loops" } else { "-" });
+        }
+        println!();
+    }
+}
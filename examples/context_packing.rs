@@ -0,0 +1,71 @@
+//! The `context_packing` fuzz target's check, run deterministically on stable Rust: at every
+//! radius on either side, from 1 to 31, packing a state, a center bit and two ngrams into a
+//! `LocalContext` must give each of them back exactly, so that distinct contexts never collide.
+//! Each radius gets the all-0 and all-1 ngrams, and then random ones from a fixed seed.
+//!
+//! Run with `cargo run --release --example context_packing -- [tuples per radius]`, which
+//! defaults to 4000, about 3.8 million tuples in all.
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use simple_ngram_bb::ngram::{LocalContext, NGram, NGramBits, Radius};
+use simple_ngram_bb::program::{Bit, State};
+
+fn check(state: State, left: NGram, center: Bit, right: NGram, radius: Radius) {
+    let context = LocalContext::from_parts(state, left, center, right, radius);
+    let unpacked = (
+        context.state,
+        context.get_left(radius),
+        context.get_center(radius),
+        context.get_right(radius),
+    );
+    assert_eq!(
+        unpacked,
+        (state, left, center, right),
+        "packing does not round-trip at radius {radius}"
+    );
+}
+
+fn main() {
+    let per_radius: u64 = std::env::args()
+        .nth(1)
+        .map_or(4000, |arg| arg.parse().expect("the argument is a number"));
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut checked: u64 = 0;
+    for left_radius in Radius::MIN..=Radius::MAX {
+        for right_radius in Radius::MIN..=Radius::MAX {
+            let radius = Radius::asymmetric(left_radius, right_radius).expect("sides are in range");
+            let fill = |len: u8| NGram(NGramBits::MAX >> (NGramBits::BITS - u32::from(len)));
+            for state in 1..=5 {
+                for center in [Bit(false), Bit(true)] {
+                    check(State(state), NGram(0), center, NGram(0), radius);
+                    check(
+                        State(state),
+                        fill(radius.left()),
+                        center,
+                        fill(radius.right()),
+                        radius,
+                    );
+                    checked += 2;
+                }
+            }
+            for _ in 0..per_radius {
+                let mut ngram = |len: u8| NGram(rng.gen::<NGramBits>() & fill(len).0);
+                let (left, right) = (ngram(radius.left()), ngram(radius.right()));
+                check(
+                    State(rng.gen_range(1..=5)),
+                    left,
+                    Bit(rng.gen()),
+                    right,
+                    radius,
+                );
+                checked += 1;
+            }
+        }
+    }
+    println!(
+        "{checked} tuples round-trip at every radius from {} to {} on either side",
+        Radius::MIN,
+        Radius::MAX
+    );
+}
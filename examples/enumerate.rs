@@ -0,0 +1,74 @@
+//! Builds random 5-state machines directly as `Program` values, without ever writing them as
+//! text, classifies them all in parallel and reports the throughput. Every looping machine's
+//! reachable set is also checked as a certificate, so the whole pipeline runs on `&Program`.
+//!
+//! Run with `cargo run --release --example enumerate -- [count] [radius] [seed]`, which defaults
+//! to 100000 machines at radius 4 with seed 0.
+
+use std::time::Instant;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use simple_ngram_bb::ngram::{classify_retaining, verify_certificate, ClassifyConfig};
+use simple_ngram_bb::program::{Bit, Dir, Program, State};
+
+/**
+ * A machine with 5 states, each transition of which halts with probability 1/10 and otherwise
+ * goes anywhere.
+ */
+fn random_program(rng: &mut impl Rng) -> Program {
+    let mut program = Program::new(5);
+    for state in 1..=5 {
+        for read in [Bit(false), Bit(true)] {
+            if rng.gen_ratio(1, 10) {
+                continue;
+            }
+            let dir = if rng.gen() { Dir::Right } else { Dir::Left };
+            let action = (State(rng.gen_range(1..=5)), Bit(rng.gen()), dir);
+            program = program.with_transition(State(state), read, action);
+        }
+    }
+    program
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut arg = |default: u64| {
+        args.next()
+            .map_or(default, |arg| arg.parse().expect("arguments are numbers"))
+    };
+    let count = arg(100_000);
+    let radius = arg(4) as u8;
+    let seed = arg(0);
+    let config = ClassifyConfig::new(radius).with_max_context_count(100_000);
+
+    let start = Instant::now();
+    let (loops, reachable_contexts) = (0..count)
+        .into_par_iter()
+        .map(|index| {
+            // One generator per machine, so the machines do not depend on the thread count.
+            let program = random_program(&mut ChaCha8Rng::seed_from_u64(seed.wrapping_add(index)));
+            let (outcome, reachable) = classify_retaining(&program, &config);
+            if outcome.is_err() {
+                return (0, 0);
+            }
+            if let Err(err) = verify_certificate(&program, &reachable) {
+                panic!("{program}: {}", err.describe(reachable.radius()));
+            }
+            (1, reachable.stats(&program).reachable_contexts)
+        })
+        .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+    let elapsed = start.elapsed();
+
+    println!("{count} machines at radius {radius} in {elapsed:.2?}");
+    println!(
+        " - loops: {loops} ({:.1}%), with {:.1} reachable contexts on average",
+        loops as f64 * 100.0 / count as f64,
+        reachable_contexts as f64 / loops.max(1) as f64
+    );
+    println!(
+        " - throughput: {:.0} machines/s",
+        count as f64 / elapsed.as_secs_f64()
+    );
+}
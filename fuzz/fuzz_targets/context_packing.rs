@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use simple_ngram_bb::ngram::{LocalContext, NGram, NGramBits, Radius};
+use simple_ngram_bb::program::{Bit, State};
+
+// Packing a state, a center bit and the ngrams on either side into a `LocalContext` must give
+// every part back exactly, at every radius. Since the parts can be read back out, two distinct
+// tuples can never pack into the same context.
+fuzz_target!(|data: [u8; 12]| {
+    let side = |byte: u8| Radius::MIN + byte % (Radius::MAX - Radius::MIN + 1);
+    let radius = Radius::asymmetric(side(data[0]), side(data[1])).expect("sides are in range");
+    let ngram = |bytes: &[u8], len: u8| {
+        let bits = NGramBits::from_le_bytes(bytes.try_into().expect("4 bytes"));
+        NGram(bits & ((1 << len) - 1))
+    };
+    let state = State(1 + data[2] % 5);
+    let center = Bit(data[3] & 1 != 0);
+    let left = ngram(&data[4..8], radius.left());
+    let right = ngram(&data[8..12], radius.right());
+
+    let context = LocalContext::from_parts(state, left, center, right, radius);
+    assert_eq!(context.state, state);
+    assert_eq!(context.get_left(radius), left);
+    assert_eq!(context.get_center(radius), center);
+    assert_eq!(context.get_right(radius), right);
+});
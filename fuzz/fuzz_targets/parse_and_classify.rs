@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use simple_ngram_bb::ngram::classify;
+use simple_ngram_bb::program::{ParseMode, Program};
+
+// Any input must either be rejected by the parser or classified without panicking.
+// The small radius and context budget keep each run quick.
+fuzz_target!(|data: &[u8]| {
+    for mode in [ParseMode::Lenient, ParseMode::Strict] {
+        if let Ok(program) = Program::try_from_bytes(data, mode) {
+            let _ = classify(&program, 2, 1_000);
+        }
+    }
+});
@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+
+use crate::program::{Bit, Program, State};
+use crate::simulate::Simulation;
+
+/**
+ * How the cycler decider ended.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum CyclerResult {
+    /**
+     * The whole configuration (state, head position and visited tape) repeated, so the machine
+     * loops forever. It first repeated after `steps` steps.
+     */
+    Cycles { steps: u64 },
+    /**
+     * The machine halted after `steps` steps, the halting transition included.
+     */
+    Halted { steps: u64 },
+    /**
+     * Neither happened within the step limit, or the head left the window.
+     */
+    Unknown,
+}
+
+/**
+ * A decider independent of the CPS one: simulates `program` from the blank tape, remembering
+ * every configuration, and reports that it loops forever once one repeats. Gives up after
+ * `max_steps` steps, or once the head has visited more than `max_window` cells.
+ *
+ * Only the visited cells need to be compared, since every other cell is still blank, and the
+ * visited span only ever grows, so equal configurations always cover the same cells.
+ */
+pub fn decide_cycler(program: &Program, max_steps: u64, max_window: usize) -> CyclerResult {
+    let mut simulation = Simulation::new(program);
+    let mut seen: HashSet<(State, usize, Vec<Bit>)> = HashSet::new();
+    while simulation.steps() < max_steps {
+        if simulation.tape().len() > max_window {
+            return CyclerResult::Unknown;
+        }
+        let configuration = (
+            simulation.state(),
+            simulation.head(),
+            simulation.tape().iter().copied().collect(),
+        );
+        if !seen.insert(configuration) {
+            return CyclerResult::Cycles {
+                steps: simulation.steps(),
+            };
+        }
+        if !simulation.step() {
+            return CyclerResult::Halted {
+                steps: simulation.steps() + 1,
+            };
+        }
+    }
+    CyclerResult::Unknown
+}
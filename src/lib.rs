@@ -0,0 +1,6 @@
+pub mod deciders;
+pub mod ngram;
+pub mod program;
+mod reachable_set;
+pub mod seed_database;
+pub mod simulate;
@@ -1,64 +1,595 @@
-mod ngram;
-mod program;
-
 use clap::Parser;
-use program::{LoopsForever, MayHalt, Program};
+use rayon::prelude::*;
+use simple_ngram_bb::deciders::{decide_cycler, CyclerResult};
+use simple_ngram_bb::ngram::{
+    classify_min_radius, classify_multi, classify_retaining, classify_with_callback,
+    classify_with_config, classify_with_stats, decided_exactly_at_with_config, ClassifyConfig,
+    ClassifyOutcome, ClassifyStats, NGram, PartialReachable, Radius,
+};
+use simple_ngram_bb::program::{
+    Bit, Dir, LoopsForever, MachineFormat, MayHalt, ParseMode, Program, State,
+};
+use simple_ngram_bb::seed_database::{SeedDatabase, SeedDatabaseError};
 
-use std::io::{Read, Seek, Write};
+use std::io::{BufWriter, Read, Write};
+use std::time::{Duration, Instant};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-    // The machine to handle, in 30-character or 34-character format.
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Classify a single machine.
+    Classify(ClassifyArgs),
+    /// Classify the machines of a seed database listed in an undecided index, writing index files.
+    RunDb(Box<RunDbArgs>),
+    /// Classify machines read from standard input, one per line.
+    Stdin(StdinArgs),
+    /// Print machines in a canonical text form, one per line, without classifying them.
+    Canonicalize(CanonicalizeArgs),
+    /// Compare two index files, counting the machines only in one of them or in both.
+    Diff(DiffArgs),
+}
+
+/**
+ * Rejects radii that `Radius` does not allow while parsing the command line, rather than
+ * panicking in the middle of a run.
+ */
+fn radius_parser() -> clap::builder::RangedI64ValueParser<u8> {
+    clap::value_parser!(u8).range(i64::from(Radius::MIN)..=i64::from(Radius::MAX))
+}
+
+/**
+ * Parses a state letter, `A` to `E` in either case.
+ */
+fn state_parser(s: &str) -> Result<State, String> {
+    match s.trim().to_ascii_uppercase().as_str() {
+        "A" => Ok(State(1)),
+        "B" => Ok(State(2)),
+        "C" => Ok(State(3)),
+        "D" => Ok(State(4)),
+        "E" => Ok(State(5)),
+        _ => Err(format!("'{s}' is not a state, which must be one of A to E")),
+    }
+}
+
+/**
+ * Options shared by every mode, controlling the decider itself.
+ */
+#[derive(clap::Args, Debug)]
+struct DeciderArgs {
+    #[clap(
+        long,
+        default_value_t = 4,
+        value_parser = radius_parser(),
+        help = "How many cells on each side of the head a local context covers."
+    )]
+    radius: u8,
+
+    #[clap(
+        long,
+        value_parser = radius_parser(),
+        conflicts_with = "radii",
+        help = "Override --radius on the left of the head only, e.g. to give a machine drifting left a large radius on that side without paying for it on the other."
+    )]
+    radius_left: Option<u8>,
+
+    #[clap(
+        long,
+        value_parser = radius_parser(),
+        conflicts_with = "radii",
+        help = "Override --radius on the right of the head only."
+    )]
+    radius_right: Option<u8>,
+
+    #[clap(
+        long,
+        value_delimiter = ',',
+        value_parser = radius_parser(),
+        help = "Classify at each of these radii (e.g. '1,2,4,8') and print one row of decisions per machine, instead of writing index files."
+    )]
+    radii: Vec<u8>,
+
+    #[clap(long, default_value_t = 1_000_000)]
+    max_context_count: usize,
+
+    #[clap(
+        long,
+        help = "Give up on a machine once its reachable structure is estimated to use this many megabytes."
+    )]
+    max_memory_mb: Option<usize>,
+
+    #[clap(long, help = "Give up on a machine after this many milliseconds.")]
+    timeout_ms: Option<u64>,
+
+    #[clap(
+        long,
+        help = "Simulate each machine for this many steps first, reporting those seen to halt as undecided straight away, along with their exact step count."
+    )]
+    presim_steps: Option<u64>,
+
+    #[clap(
+        long,
+        help = "For every machine declared looping, check that none of its reachable contexts halt, panicking if one does."
+    )]
+    check_soundness: bool,
+
+    #[clap(
+        long,
+        help = "After saturating each machine, check that every reachable context has its left or right ngram among the reachable ngrams on that side, panicking if not."
+    )]
+    self_check: bool,
+
+    #[clap(
+        long,
+        help = "Also classify each machine's mirror image, with left and right swapped, panicking if only one of the two is declared looping."
+    )]
+    check_mirror: bool,
+
+    #[clap(
+        long,
+        help = "For every machine declared looping, write its reachable set out as a certificate, read it back and verify that it is closed and never halts, panicking if not."
+    )]
+    verify_each: bool,
+
+    #[clap(
+        long,
+        value_delimiter = ',',
+        value_parser = state_parser,
+        help = "Classify as if the transitions out of these states (e.g. 'C,E') were halting, to see how the decision depends on them."
+    )]
+    treat_as_halt: Vec<State>,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u8).range(0..=1),
+        help = "The symbol every cell of the tape starts out as, for machine databases where the blank is 1 rather than 0."
+    )]
+    blank_symbol: u8,
+
+    #[clap(
+        long,
+        help = "Reject a machine the moment a reachable context that halts is found, rather than when saturation reaches it. The looping machines are the same; only rejecting the others is quicker."
+    )]
+    fast_reject: bool,
+
+    #[clap(
+        long,
+        help = "Treat --radius as a cap: try radius 1, 2, 4, ... up to it, stopping at the first that decides the machine."
+    )]
+    auto_cap_radius: bool,
+}
+
+impl DeciderArgs {
+    fn config(&self) -> ClassifyConfig {
+        let mut config = ClassifyConfig::new(self.radius)
+            .with_max_context_count(self.max_context_count)
+            .with_check_soundness(self.check_soundness)
+            .with_self_check(self.self_check)
+            .with_check_mirror(self.check_mirror)
+            .with_verify_each(self.verify_each)
+            .with_treat_as_halt(&self.treat_as_halt)
+            .with_blank(Bit(self.blank_symbol == 1))
+            .with_fast_reject(self.fast_reject)
+            .with_auto_cap_radius(self.auto_cap_radius);
+        if let Some(radius_left) = self.radius_left {
+            config = config.with_radius_left(radius_left);
+        }
+        if let Some(radius_right) = self.radius_right {
+            config = config.with_radius_right(radius_right);
+        }
+        if let Some(max_memory_mb) = self.max_memory_mb {
+            config = config.with_max_memory_bytes(max_memory_mb * 1024 * 1024);
+        }
+        if let Some(timeout_ms) = self.timeout_ms {
+            config = config.with_timeout(Duration::from_millis(timeout_ms));
+        }
+        if let Some(presim_steps) = self.presim_steps {
+            config = config.with_presim_steps(presim_steps);
+        }
+        config
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct ParseArgs {
+    #[clap(
+        long,
+        help = "Only accept '---' as a halting transition (or three 0 bytes in a raw seed database record), rejecting machines that use '-', 'Z', 'H' or 0 in any other way, or that mix text and raw bytes."
+    )]
+    strict_format: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct OutputArgs {
+    #[clap(
+        long,
+        conflicts_with = "verbose",
+        help = "Only print the final results, without any progress lines."
+    )]
+    quiet: bool,
+
     #[clap(
         short,
         long,
-        value_parser,
-        default_value_t = String::new(),
-        help = "A machine, as either a 34-character string '1RB0LC_0LA1RD_1LA0RB_1LE---_0RA1RE' or a 30-character string like '1RB0LC0LA1RD1LA0RB1LE---0RA1RE'."
+        action = clap::ArgAction::Count,
+        help = "Print more about the run; once (-v) also echoes the parsed command line first."
+    )]
+    verbose: u8,
+}
+
+impl ParseArgs {
+    fn mode(&self) -> ParseMode {
+        if self.strict_format {
+            ParseMode::Strict
+        } else {
+            ParseMode::Lenient
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct ClassifyArgs {
+    #[clap(
+        help = "A machine with 1 to 5 states, as either a string like '1RB0LC_0LA1RD_1LA0RB_1LE---_0RA1RE' or a packed string like '1RB0LC0LA1RD1LA0RB1LE---0RA1RE'."
     )]
     machine: String,
 
-    #[clap(long, default_value_t = String::new())]
+    #[clap(
+        long,
+        value_parser = radius_parser(),
+        conflicts_with_all = ["radii", "dump_cps", "path_to_halt", "radius_left", "radius_right"],
+        help = "Classify at every radius from 1 to this one in parallel, and report the smallest that decides the machine."
+    )]
+    parallel_radius: Option<u8>,
+
+    #[clap(
+        long,
+        help = "Show the number of reachable contexts on stderr as it grows."
+    )]
+    show_progress: bool,
+
+    #[clap(
+        long,
+        help = "Print the reachable structure that was built: its contexts per state, its ngrams and every context."
+    )]
+    dump_cps: bool,
+
+    #[clap(
+        long,
+        help = "If a reachable context halts, print a shortest path of reachable contexts from the blank start context to it."
+    )]
+    path_to_halt: bool,
+
+    #[clap(flatten)]
+    output: OutputArgs,
+
+    #[clap(flatten)]
+    parse: ParseArgs,
+
+    #[clap(flatten)]
+    decider: DeciderArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct RunDbArgs {
+    #[clap(
+        long,
+        help = "The bbchallenge seed database: a 30-byte header, then 30 bytes per machine."
+    )]
     seed_database: String,
 
-    #[clap(long, default_value_t = String::new())]
-    undecided_index: String,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "The machines to classify, as 4-byte big-endian indices into the seed database. Several index files can be given, separated by commas or by repeating the option; they are read one after the other, and their results all go to the same outputs. Without any, every machine of the seed database is classified."
+    )]
+    undecided_index: Vec<String>,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "Number of worker threads (0 uses one per core). Output order does not depend on it."
+    )]
+    threads: usize,
 
-    #[clap(long, default_value_t = 4)]
-    radius: u8,
+    #[clap(
+        long,
+        help = "Only write the undecided index (index-undecided-n-{radius} by default), the machines still undecided at this radius. It can be passed as --undecided-index to a run at a larger radius."
+    )]
+    undecided_only: bool,
 
-    #[clap(long, default_value_t = 1_000_000)]
-    max_context_count: usize,
+    #[clap(
+        long,
+        default_value = ".",
+        help = "Write the output index files into this directory, creating it if needed."
+    )]
+    output_dir: std::path::PathBuf,
+
+    #[clap(
+        long,
+        default_value = "index-looping-n-{radius}",
+        help = "File name of the looping machines' index. '{radius}' and '{max_context_count}' are replaced by the settings of the run; an asymmetric radius is written with both sides, like '3l5r'."
+    )]
+    looping_name: String,
+
+    #[clap(
+        long,
+        default_value = "index-undecided-n-{radius}",
+        help = "File name of the undecided machines' index, with the same placeholders as --looping-name."
+    )]
+    undecided_name: String,
+
+    #[clap(
+        long,
+        help = "Read the seed database with seek/read calls instead of memory-mapping it."
+    )]
+    no_mmap: bool,
+
+    #[clap(
+        long,
+        help = "Write the output index files sorted ascending, instead of in input order. The indices are kept in memory until the end of the run, at 4 bytes per machine."
+    )]
+    sort_output: bool,
+
+    #[clap(
+        long,
+        requires = "radii",
+        help = "Run each of --radii in turn, each on the machines still undecided after the previous one, writing the output index files of every radius. Each radius's looping index then holds the machines first decided at that radius."
+    )]
+    chain: bool,
+
+    #[clap(
+        long,
+        requires = "radii",
+        conflicts_with = "chain",
+        help = "For each of --radii, count the machines decided exactly at that radius: looping there, but not one radius lower. Nothing is written but this table."
+    )]
+    frontier: bool,
+
+    #[clap(
+        long,
+        help = "Also write the looping machines' indices to this file in the bbchallenge decider index layout: 4-byte big-endian indices, sorted ascending without duplicates, and no header."
+    )]
+    bbchallenge_output: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        help = "Also write a bitmap with one bit per machine of the whole seed database, set for the looping machines: machine i is bit i % 8 (least significant first) of byte i / 8."
+    )]
+    bitmap_output: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with = "frontier",
+        help = "Also write one JSON object per classified machine to this file, one per line, as machines are decided: its index, machine, radius, decision, reason (null if looping), reachable context count and classification time in microseconds. With --chain, each radius adds a line for every machine it classifies. Not written for a --radii table without --chain."
+    )]
+    ndjson: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        help = "Also run an independent cycler decider on every machine, reporting how the two overlap and flagging any machine the CPS decider calls looping that the cycler sees halt."
+    )]
+    cross_check: bool,
+
+    #[clap(
+        long,
+        help = "Only classify this many machines, chosen uniformly at random from the undecided indices, and estimate the looping share of all of them. With --chain, the later radii run on what the sample leaves undecided."
+    )]
+    sample: Option<usize>,
+
+    #[clap(
+        long,
+        requires = "sample",
+        help = "Seed the random choice of --sample, to choose the same machines again. Without it, a seed is picked at random and printed."
+    )]
+    seed: Option<u64>,
+
+    #[clap(flatten)]
+    output: OutputArgs,
+
+    #[clap(flatten)]
+    parse: ParseArgs,
+
+    #[clap(flatten)]
+    decider: DeciderArgs,
 }
 
-fn main() -> Result<(), i32> {
-    use ngram::classify as classify_fn;
+#[derive(clap::Args, Debug)]
+struct StdinArgs {
+    #[clap(flatten)]
+    output: OutputArgs,
 
-    use std::time::Instant;
+    #[clap(flatten)]
+    parse: ParseArgs,
 
-    let start_time = Instant::now();
+    #[clap(flatten)]
+    decider: DeciderArgs,
+}
 
-    let args = Args::parse();
-    println!("args: {:?}", args);
-
-    if !args.seed_database.is_empty() {
-        let mut output_file_looping =
-            std::fs::File::create(format!("index-looping-n-{}", args.radius))
-                .expect("can create index-looping-n-{}");
-        let mut output_file_halting =
-            std::fs::File::create(format!("index-undecided-n-{}", args.radius))
-                .expect("can create index-undecided-n-{}");
-
-        let mut seed_database =
-            std::fs::File::open(args.seed_database).expect("--seed_database can be opened");
-        let mut previously_undecided_index =
-            std::fs::File::open(args.undecided_index).expect("--undecided_index can be opened");
+#[derive(clap::Args, Debug)]
+struct CanonicalizeArgs {
+    #[clap(
+        help = "The machine to canonicalize, in any format `classify` accepts. Machines are read from standard input, one per line, if this is absent."
+    )]
+    machine: Option<String>,
+
+    #[clap(
+        long,
+        help = "Print the packed form like '1RB0LC0LA1RD1LA0RB1LE---0RA1RE' instead of the `_`-separated one."
+    )]
+    packed: bool,
+
+    #[clap(
+        long,
+        help = "Print the machine's tree normal form: states renamed in the order they are first entered from the blank tape, and directions flipped if A0 moves left."
+    )]
+    tnf: bool,
+
+    #[clap(
+        long,
+        default_value_t = 100_000,
+        help = "How many steps --tnf simulates while looking for the order states are entered in."
+    )]
+    tnf_steps: u64,
+
+    #[clap(flatten)]
+    parse: ParseArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    #[clap(help = "An index file of 4-byte big-endian machine indices, sorted or not.")]
+    left: std::path::PathBuf,
+
+    #[clap(help = "The index file to compare it with.")]
+    right: std::path::PathBuf,
+
+    #[clap(
+        long,
+        help = "Also print every index, one per line after its category: 'left-only', 'right-only' or 'both'."
+    )]
+    list: bool,
+}
+
+/**
+ * How many machines are read from the database and classified (in parallel) at once.
+ * Results are always written back in the order of the undecided index.
+ */
+const CHUNK_SIZE: usize = 4096;
+
+/**
+ * A uniformly random choice of `size` of the machines listed in the undecided indices.
+ */
+#[derive(Copy, Clone, Debug)]
+struct Sample {
+    size: usize,
+    seed: u64,
+}
+
+impl Sample {
+    fn from_args(args: &RunDbArgs) -> Option<Sample> {
+        args.sample.map(|size| Sample {
+            size,
+            seed: args.seed.unwrap_or_else(rand::random),
+        })
+    }
+
+    /**
+     * The positions of the chosen entries among `entry_count`, in ascending order. ChaCha8 is
+     * used for its output being fixed across versions, so a seed always picks the same entries.
+     */
+    fn choose(self, entry_count: usize) -> Vec<usize> {
+        use rand::SeedableRng;
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(self.seed);
+        let mut chosen =
+            rand::seq::index::sample(&mut rng, entry_count, self.size.min(entry_count)).into_vec();
+        chosen.sort_unstable();
+        chosen
+    }
+}
+
+/**
+ * What `for_each_database_chunk` read.
+ */
+struct DatabaseRead {
+    /**
+     * How many entries the indices hold in all, whether sampled or not.
+     */
+    entry_count: usize,
+    /**
+     * The time spent reading and parsing the machines' records.
+     */
+    parse_time: Duration,
+}
+
+/**
+ * Reads each machine listed in the undecided indices out of the seed database, in index order
+ * (one index file after the other), handing them to the callback in chunks of at most
+ * `CHUNK_SIZE` `(machine_index, program)` pairs. With no undecided indices, every machine of
+ * the database is read, in order. With a `sample`, only the chosen entries are read.
+ * Fails, without reading any further, on an index that lies past the end of the database
+ * or on a record that `mode` does not accept, and before reading anything if the database's
+ * length and header disagree, or an index is not a whole number of 4-byte entries.
+ */
+fn for_each_database_chunk(
+    seed_database_path: &str,
+    undecided_indices: &[String],
+    use_mmap: bool,
+    mode: ParseMode,
+    sample: Option<Sample>,
+    mut handle: impl FnMut(&[(u32, Program)]),
+) -> Result<DatabaseRead, String> {
+    let mut seed_database =
+        SeedDatabase::open(seed_database_path, use_mmap).map_err(|err| {
+            format!(
+                "cannot read --seed-database {seed_database_path}: {err}; is --seed-database pointing at the right file?"
+            )
+        })?;
+    let mut entry_count = 0;
+    for undecided_index in undecided_indices {
+        let undecided_index_len = std::fs::metadata(undecided_index)
+            .map_err(|err| format!("cannot read --undecided-index {undecided_index}: {err}"))?
+            .len();
+        if !undecided_index_len.is_multiple_of(4) {
+            return Err(format!(
+                "{undecided_index} is {undecided_index_len} bytes long, which is not a whole number of 4-byte machine indices"
+            ));
+        }
+        if undecided_index_len == 0 {
+            eprintln!("{undecided_index} is empty, so there are no machines to classify in it");
+        }
+        entry_count += (undecided_index_len / 4) as usize;
+    }
+    if undecided_indices.is_empty() {
+        entry_count = seed_database.len() as usize;
+    }
+    let mut chosen = sample
+        .map(|sample| sample.choose(entry_count))
+        .map(|chosen| chosen.into_iter().peekable());
 
-        let mut count_processed = 0;
-        let mut count_loops = 0;
-        let mut count_undecided = 0;
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    let mut position = 0;
+    let mut parse_time = Duration::ZERO;
+    // Reads the next entry, `machine_index` from `source`, unless the sample leaves it out.
+    let mut read_entry = |machine_index: u32, source: &str| -> Result<(), String> {
+        position += 1;
+        if let Some(chosen) = &mut chosen {
+            if chosen.next_if_eq(&(position - 1)).is_none() {
+                return Ok(());
+            }
+        }
+        let parse_start = Instant::now();
+        let machine = seed_database
+            .get(machine_index, mode)
+            .map_err(|err| match err {
+                SeedDatabaseError::OutOfRange { .. } => format!(
+                    "{source}: {err} in {seed_database_path}; is the index file paired with the right database?"
+                ),
+                err => format!("{seed_database_path}: {err}"),
+            })?;
+        parse_time += parse_start.elapsed();
 
+        chunk.push((machine_index, machine));
+        if chunk.len() == CHUNK_SIZE {
+            handle(&chunk);
+            chunk.clear();
+        }
+        Ok(())
+    };
+    if undecided_indices.is_empty() {
+        for machine_index in 0..entry_count as u32 {
+            read_entry(machine_index, seed_database_path)?;
+        }
+    }
+    for undecided_index in undecided_indices {
+        let mut previously_undecided_index =
+            std::fs::File::open(undecided_index).expect("--undecided_index can be opened");
         loop {
             let mut machine_index_bytes_be: [u8; 4] = [0; 4];
             let count_read = previously_undecided_index
@@ -70,74 +601,1385 @@ fn main() -> Result<(), i32> {
             if count_read != 4 {
                 panic!("invalid");
             }
-            let machine_index = u32::from_be_bytes(machine_index_bytes_be);
-            seed_database
-                .seek(std::io::SeekFrom::Start((machine_index + 1) as u64 * 30))
-                .expect("seed succeeded");
-
-            let mut machine_bytes: [u8; 30] = [0; 30];
-            let count = seed_database
-                .read(&mut machine_bytes)
-                .expect("read succeeds");
-            if count != machine_bytes.len() {
-                panic!(
-                    "unexpected read; only got {} of {} expected for machine_index={machine_index}",
-                    count,
-                    machine_bytes.len()
-                );
+            read_entry(u32::from_be_bytes(machine_index_bytes_be), undecided_index)?;
+        }
+    }
+    if !chunk.is_empty() {
+        handle(&chunk);
+    }
+    Ok(DatabaseRead {
+        entry_count,
+        parse_time,
+    })
+}
+
+/**
+ * An output index file, written either as machines are decided or, when sorting, all at once
+ * when the run finishes.
+ */
+struct IndexOutput {
+    file: BufWriter<std::fs::File>,
+    // With --sort-output, everything pushed so far, which is not written until `finish`.
+    pending: Option<Vec<u32>>,
+}
+
+impl IndexOutput {
+    fn create(path: &std::path::Path, sorted: bool) -> Self {
+        IndexOutput {
+            file: BufWriter::new(std::fs::File::create(path).unwrap_or_else(|err| {
+                panic!("can create {}: {err}", path.display());
+            })),
+            pending: sorted.then(Vec::new),
+        }
+    }
+
+    fn push(&mut self, machine_index: u32) {
+        match &mut self.pending {
+            Some(pending) => pending.push(machine_index),
+            None => self
+                .file
+                .write_all(&machine_index.to_be_bytes())
+                .expect("ok"),
+        }
+    }
+
+    fn flush(&mut self) {
+        self.file.flush().expect("can flush");
+    }
+
+    fn finish(&mut self) {
+        if let Some(mut pending) = self.pending.take() {
+            pending.sort_unstable();
+            for machine_index in pending {
+                self.file
+                    .write_all(&machine_index.to_be_bytes())
+                    .expect("ok");
             }
+        }
+        self.flush();
+    }
+}
+
+/**
+ * Writes `machine_indices` in the layout bbchallenge uses for a decider's index file: each index
+ * as 4 big-endian bytes, sorted ascending and without duplicates, with no header.
+ */
+fn write_bbchallenge_index(
+    path: &std::path::Path,
+    mut machine_indices: Vec<u32>,
+) -> std::io::Result<()> {
+    machine_indices.sort_unstable();
+    machine_indices.dedup();
+    let mut output = BufWriter::new(std::fs::File::create(path)?);
+    for machine_index in machine_indices {
+        output.write_all(&machine_index.to_be_bytes())?;
+    }
+    output.flush()
+}
 
-            let machine = Program::from_string(
-                std::str::from_utf8(&machine_bytes).expect("valid utf8, barely"),
+/**
+ * Writes a bitmap with one bit per machine of a seed database holding `machine_count` machines,
+ * set for each of `machine_indices`: machine `i` is bit `i % 8` (least significant first) of
+ * byte `i / 8`, and the unused bits of the last byte are 0.
+ */
+fn write_bitmap(
+    path: &std::path::Path,
+    machine_count: u32,
+    machine_indices: &[u32],
+) -> std::io::Result<()> {
+    let mut bitmap = vec![0u8; (machine_count as usize).div_ceil(8)];
+    for &machine_index in machine_indices {
+        bitmap[machine_index as usize / 8] |= 1 << (machine_index % 8);
+    }
+    std::fs::write(path, bitmap)
+}
+
+/**
+ * A JSON string literal holding `s`.
+ */
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/**
+ * The `--ndjson` output: one JSON object per classified machine, one per line, written as
+ * machines are decided.
+ */
+struct NdjsonOutput {
+    file: BufWriter<std::fs::File>,
+}
+
+impl NdjsonOutput {
+    fn create(path: &std::path::Path) -> Self {
+        NdjsonOutput {
+            file: BufWriter::new(std::fs::File::create(path).unwrap_or_else(|err| {
+                panic!("can create {}: {err}", path.display());
+            })),
+        }
+    }
+
+    fn push(
+        &mut self,
+        machine_index: u32,
+        machine: &Program,
+        radius: Radius,
+        outcome: ClassifyOutcome,
+        stats: &ClassifyStats,
+        elapsed: Duration,
+    ) {
+        let (decision, reason) = match outcome {
+            Ok(LoopsForever) => ("loops", "null".to_string()),
+            Err(reason) => ("undecided", json_string(&reason.to_string())),
+        };
+        writeln!(
+            self.file,
+            "{{\"index\": {machine_index}, \"machine\": {}, \"radius\": {}, \"decision\": \"{decision}\", \"reason\": {reason}, \"reachable_contexts\": {}, \"requeues\": {}, \"revisited_contexts\": {}, \"elapsed_micros\": {}}}",
+            json_string(&machine.to_string()),
+            json_string(&radius.to_string()),
+            stats.reachable_contexts,
+            stats.requeues,
+            stats.revisited_contexts,
+            elapsed.as_micros()
+        )
+        .expect("ok");
+    }
+
+    fn flush(&mut self) {
+        self.file.flush().expect("can flush");
+    }
+}
+
+/**
+ * `manifest.json`, written into `--output-dir` by the runs that write index files: the
+ * arguments and inputs of the run and what it found, so that its outputs can be traced back to
+ * what made them. It is written when the run starts, with `"status": "running"`, rewritten
+ * after each pass, and a last time once the run is `"complete"` (or has `"failed"`).
+ */
+struct Manifest {
+    path: std::path::PathBuf,
+    args: Vec<String>,
+    seed_database: String,
+    seed_database_bytes: Option<u64>,
+    seed_database_records: Option<u32>,
+    // Each undecided index with its entry count, empty when the whole database is run.
+    undecided_indices: Vec<(String, Option<u64>)>,
+    extra_outputs: Vec<std::path::PathBuf>,
+    start_time: u64,
+    end_time: Option<u64>,
+    status: &'static str,
+    passes: Vec<ManifestPass>,
+}
+
+struct ManifestPass {
+    radius: Radius,
+    count_processed: usize,
+    count_loops: usize,
+    count_undecided: usize,
+    outputs: Vec<std::path::PathBuf>,
+}
+
+/**
+ * Seconds since the Unix epoch.
+ */
+fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+impl Manifest {
+    fn start(args: &RunDbArgs) -> Self {
+        let manifest = Manifest {
+            path: args.output_dir.join("manifest.json"),
+            args: std::env::args().collect(),
+            seed_database: args.seed_database.clone(),
+            seed_database_bytes: std::fs::metadata(&args.seed_database)
+                .ok()
+                .map(|metadata| metadata.len()),
+            seed_database_records: SeedDatabase::open(&args.seed_database, !args.no_mmap)
+                .ok()
+                .map(|database| database.len()),
+            undecided_indices: args
+                .undecided_index
+                .iter()
+                .map(|path| {
+                    let entries = std::fs::metadata(path)
+                        .ok()
+                        .map(|metadata| metadata.len() / 4);
+                    (path.clone(), entries)
+                })
+                .collect(),
+            extra_outputs: [&args.ndjson, &args.bbchallenge_output, &args.bitmap_output]
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect(),
+            start_time: unix_time(),
+            end_time: None,
+            status: "running",
+            passes: Vec::new(),
+        };
+        std::fs::create_dir_all(&args.output_dir).expect("can create --output-dir");
+        manifest.write();
+        manifest
+    }
+
+    fn add_pass(&mut self, pass: &PassSummary) {
+        self.passes.push(ManifestPass {
+            radius: pass.radius,
+            count_processed: pass.count_processed,
+            count_loops: pass.count_loops,
+            count_undecided: pass.count_undecided,
+            outputs: pass
+                .looping_path
+                .iter()
+                .chain([&pass.undecided_path])
+                .cloned()
+                .collect(),
+        });
+        self.write();
+    }
+
+    fn finish(&mut self, ok: bool) {
+        self.status = if ok { "complete" } else { "failed" };
+        self.end_time = Some(unix_time());
+        self.write();
+    }
+
+    /**
+     * Replaces the file as a whole, through a temporary file, so that it can be read at any time.
+     */
+    fn write(&self) {
+        let json_path = |path: &std::path::PathBuf| json_string(&path.to_string_lossy());
+        let json_option = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+        let json_list = |items: Vec<String>| format!("[{}]", items.join(", "));
+
+        let undecided_indices = self
+            .undecided_indices
+            .iter()
+            .map(|(path, entries)| {
+                format!(
+                    "{{\"path\": {}, \"entries\": {}}}",
+                    json_string(path),
+                    json_option(entries.map(|entries| entries.to_string()))
+                )
+            })
+            .collect();
+        let passes = self
+            .passes
+            .iter()
+            .map(|pass| {
+                format!(
+                    "{{\"radius\": {}, \"processed\": {}, \"loops\": {}, \"undecided\": {}, \"outputs\": {}}}",
+                    json_string(&pass.radius.to_string()),
+                    pass.count_processed,
+                    pass.count_loops,
+                    pass.count_undecided,
+                    json_list(pass.outputs.iter().map(json_path).collect())
+                )
+            })
+            .collect();
+        // Later passes only see what earlier ones left undecided, so the run processed what the
+        // first pass did, and left undecided what the last one did.
+        let totals = match (self.passes.first(), self.passes.last()) {
+            (Some(first), Some(last)) => format!(
+                "{{\"processed\": {}, \"loops\": {}, \"undecided\": {}}}",
+                first.count_processed,
+                self.passes
+                    .iter()
+                    .map(|pass| pass.count_loops)
+                    .sum::<usize>(),
+                last.count_undecided
+            ),
+            _ => "null".to_string(),
+        };
+        let outputs = self
+            .passes
+            .iter()
+            .flat_map(|pass| &pass.outputs)
+            .chain(&self.extra_outputs)
+            .map(json_path)
+            .collect();
+
+        let contents = format!(
+            "{{\n  \"status\": \"{}\",\n  \"version\": \"{}\",\n  \"args\": {},\n  \"seed_database\": {{\"path\": {}, \"bytes\": {}, \"records\": {}}},\n  \"undecided_indices\": {},\n  \"start_time_unix\": {},\n  \"end_time_unix\": {},\n  \"totals\": {},\n  \"passes\": {},\n  \"outputs\": {}\n}}\n",
+            self.status,
+            env!("CARGO_PKG_VERSION"),
+            json_list(self.args.iter().map(|arg| json_string(arg)).collect()),
+            json_string(&self.seed_database),
+            json_option(self.seed_database_bytes.map(|bytes| bytes.to_string())),
+            json_option(self.seed_database_records.map(|records| records.to_string())),
+            json_list(undecided_indices),
+            self.start_time,
+            json_option(self.end_time.map(|end_time| end_time.to_string())),
+            totals,
+            json_list(passes),
+            json_list(outputs)
+        );
+        let temporary_path = self.path.with_extension("json.tmp");
+        std::fs::write(&temporary_path, contents)
+            .and_then(|()| std::fs::rename(&temporary_path, &self.path))
+            .unwrap_or_else(|err| panic!("can write {}: {err}", self.path.display()));
+    }
+}
+
+/**
+ * Runs `run` with a `Manifest` of it, which is then marked complete or failed by the result.
+ */
+fn with_manifest(
+    args: &RunDbArgs,
+    run: impl FnOnce(&mut Manifest) -> Result<(), i32>,
+) -> Result<(), i32> {
+    let mut manifest = Manifest::start(args);
+    let result = run(&mut manifest);
+    manifest.finish(result.is_ok());
+    result
+}
+
+fn print_spectrum_header(radii: &[u8]) {
+    print!("{:<36}", "machine");
+    for radius in radii {
+        print!(" {:>5}", format!("n={radius}"));
+    }
+    println!();
+}
+
+fn print_spectrum_row(label: &str, outcomes: &[ClassifyOutcome]) {
+    print!("{label:<36}");
+    for outcome in outcomes {
+        match outcome {
+            Ok(LoopsForever) => print!(" {:>5}", "loops"),
+            Err(_) => print!(" {:>5}", "-"),
+        }
+    }
+    println!();
+}
+
+/**
+ * Counts machines by the order of magnitude of their final reachable-context count
+ * (1-10, 11-100, 101-1000, ...), separately for looping and undecided machines.
+ */
+#[derive(Default)]
+struct ContextSizeHistogram {
+    // bucket -> (looping, undecided)
+    buckets: std::collections::BTreeMap<u32, (usize, usize)>,
+}
+
+impl ContextSizeHistogram {
+    fn add(&mut self, reachable_contexts: usize, outcome: ClassifyOutcome) {
+        let mut bucket = 0;
+        let mut upper: usize = 10;
+        while reachable_contexts > upper {
+            upper = upper.saturating_mul(10);
+            bucket += 1;
+        }
+        let counts = self.buckets.entry(bucket).or_default();
+        match outcome {
+            Ok(LoopsForever) => counts.0 += 1,
+            Err(_) => counts.1 += 1,
+        }
+    }
+
+    fn print(&self) {
+        println!(
+            "{:<23} {:>8} {:>10}",
+            "reachable contexts:", "loops", "undecided"
+        );
+        for (&bucket, &(loops, undecided)) in self.buckets.iter() {
+            let lower = if bucket == 0 {
+                1
+            } else {
+                10usize.pow(bucket) + 1
+            };
+            let upper = 10usize.pow(bucket + 1);
+            println!(
+                " - {:<20} {loops:>8} {undecided:>10}",
+                format!("{lower}-{upper}")
             );
+        }
+    }
+}
 
-            count_processed += 1;
-            match classify_fn(&machine, args.radius, args.max_context_count) {
-                Ok(LoopsForever) => {
-                    count_loops += 1;
-                    let count = output_file_looping
-                        .write(&machine_index_bytes_be)
-                        .expect("ok");
-                    assert!(count == machine_index_bytes_be.len());
-                }
-                Err(MayHalt) => {
-                    count_undecided += 1;
-                    let count = output_file_halting
-                        .write(&machine_index_bytes_be)
-                        .expect("ok");
-                    assert!(count == machine_index_bytes_be.len());
-                }
+/**
+ * The distinct left and right ngrams found reachable over all machines of a run,
+ * along with the most found for any single machine.
+ */
+#[derive(Default)]
+struct NGramTally {
+    left_ngrams: std::collections::BTreeSet<NGram>,
+    right_ngrams: std::collections::BTreeSet<NGram>,
+    max_left_ngrams: usize,
+    max_right_ngrams: usize,
+}
+
+impl NGramTally {
+    fn add(&mut self, stats: &ClassifyStats) {
+        self.left_ngrams.extend(stats.left_ngrams.iter().copied());
+        self.right_ngrams.extend(stats.right_ngrams.iter().copied());
+        self.max_left_ngrams = self.max_left_ngrams.max(stats.reachable_left_ngrams);
+        self.max_right_ngrams = self.max_right_ngrams.max(stats.reachable_right_ngrams);
+    }
+
+    fn print(&self) {
+        println!(
+            "{:<23} {:>8} {:>12}",
+            "reachable ngrams:", "distinct", "max/machine"
+        );
+        println!(
+            " - {:<20} {:>8} {:>12}",
+            "left",
+            self.left_ngrams.len(),
+            self.max_left_ngrams
+        );
+        println!(
+            " - {:<20} {:>8} {:>12}",
+            "right",
+            self.right_ngrams.len(),
+            self.max_right_ngrams
+        );
+    }
+}
+
+/**
+ * Which transitions the reachable contexts of a run's machines read, over all of them, and how
+ * many distinct transitions each looping machine read.
+ */
+#[derive(Default)]
+struct TransitionUse {
+    state_count: u8,
+    used: std::collections::BTreeSet<(State, Bit)>,
+    looping_by_used: std::collections::BTreeMap<usize, usize>,
+}
+
+impl TransitionUse {
+    fn add(&mut self, program: &Program, stats: &ClassifyStats, outcome: ClassifyOutcome) {
+        self.state_count = self.state_count.max(program.state_count);
+        self.used.extend(stats.transitions_used.iter().copied());
+        if outcome.is_ok() {
+            *self
+                .looping_by_used
+                .entry(stats.transitions_used.len())
+                .or_default() += 1;
+        }
+    }
+
+    fn print(&self) {
+        let never_used: Vec<String> = (1..=self.state_count)
+            .flat_map(|state| [(State(state), Bit(false)), (State(state), Bit(true))])
+            .filter(|transition| !self.used.contains(transition))
+            .map(|(state, bit)| format!("{state}{bit}"))
+            .collect();
+        if never_used.is_empty() {
+            println!("every transition is read by some machine");
+        } else {
+            println!(
+                "transitions never read by any machine: {}",
+                never_used.join(" ")
+            );
+        }
+        if !self.looping_by_used.is_empty() {
+            println!("looping machines by transitions read:");
+            for (used, count) in &self.looping_by_used {
+                println!(" - {used:<20} {count:>8}");
             }
+        }
+    }
+}
+
+/**
+ * The step and tape limits of the cycler decider that `--cross-check` compares against.
+ */
+const CROSS_CHECK_MAX_STEPS: u64 = 10_000;
+const CROSS_CHECK_MAX_WINDOW: usize = 256;
+
+/**
+ * The machine the CPS decider classifies with `config`, as the cycler has to run it for the two
+ * to judge the same machine: with the `treat_as_halt` states halting and, since the cycler only
+ * runs over blank 0s, inverted when the blank is 1.
+ */
+fn cross_check_program(machine: &Program, config: &ClassifyConfig) -> Program {
+    let restricted = machine.with_halting_states(&config.treat_as_halt);
+    if config.blank.0 {
+        restricted.invert_bits()
+    } else {
+        restricted
+    }
+}
+
+/**
+ * How the CPS decider's results compare with the cycler's over a run.
+ */
+#[derive(Default)]
+struct CrossCheck {
+    both_loop: usize,
+    only_cps_loops: usize,
+    only_cycler_loops: usize,
+    // Machines the CPS decider calls looping but the cycler sees halt.
+    conflicts: Vec<u32>,
+}
 
-            if count_processed % 100 == 0 {
-                println!(
-                    "processed {} :: {}% are looping",
-                    count_processed,
-                    count_loops * 100 / count_processed
+impl CrossCheck {
+    fn add(&mut self, machine_index: u32, outcome: ClassifyOutcome, cycler: CyclerResult) {
+        match (outcome, cycler) {
+            (Ok(LoopsForever), CyclerResult::Cycles { .. }) => self.both_loop += 1,
+            (Ok(LoopsForever), CyclerResult::Halted { steps }) => {
+                eprintln!(
+                    "CONFLICT: machine {machine_index} was decided looping, but halts after {steps} steps"
                 );
+                self.conflicts.push(machine_index);
+            }
+            (Ok(LoopsForever), CyclerResult::Unknown) => self.only_cps_loops += 1,
+            (Err(_), CyclerResult::Cycles { .. }) => self.only_cycler_loops += 1,
+            (Err(_), _) => {}
+        }
+    }
+
+    fn print(&self) {
+        println!("cross-check against the cycler:");
+        println!(" - both loop:        {:>8}", self.both_loop);
+        println!(" - only CPS loops:   {:>8}", self.only_cps_loops);
+        println!(" - only cycler:      {:>8}", self.only_cycler_loops);
+        println!(" - conflicts:        {:>8}", self.conflicts.len());
+        for machine_index in &self.conflicts {
+            println!("   - {machine_index}");
+        }
+    }
+}
+
+/**
+ * For the looping machines of a run, how large a share of all possible local contexts turned
+ * out to be reachable, and at which radius they were decided (which only varies with
+ * `--auto-cap-radius`).
+ */
+#[derive(Default)]
+struct RadiusUse {
+    looping: usize,
+    context_ratio_sum: f64,
+    context_ratio_max: f64,
+    decided_at_radius: std::collections::BTreeMap<Radius, usize>,
+}
+
+impl RadiusUse {
+    fn add(&mut self, stats: &ClassifyStats) {
+        let ratio = stats.context_ratio();
+        self.looping += 1;
+        self.context_ratio_sum += ratio;
+        self.context_ratio_max = self.context_ratio_max.max(ratio);
+        *self.decided_at_radius.entry(stats.radius).or_default() += 1;
+    }
+
+    fn print(&self) {
+        if self.looping == 0 {
+            return;
+        }
+        println!(
+            "reachable / possible contexts of looping machines: mean {:.3e}, max {:.3e}",
+            self.context_ratio_sum / self.looping as f64,
+            self.context_ratio_max
+        );
+        if self.decided_at_radius.len() > 1 {
+            println!("looping machines by deciding radius:");
+            for (radius, count) in &self.decided_at_radius {
+                println!(" - {radius:<20} {count:>8}");
             }
         }
+    }
+}
 
-        println!("done");
-        println!(" - total:      {count_processed:>8}");
-        println!(" - loops:      {count_loops:>8}");
-        println!(" - undecided:  {count_undecided:>8}");
+/**
+ * How often saturation went back over contexts it had already explored, over all the machines
+ * of a run, and the machine it happened to most.
+ */
+#[derive(Default)]
+struct Churn {
+    machines: usize,
+    requeues: usize,
+    revisited_contexts: usize,
+    worst: Option<(usize, u32)>,
+}
+
+impl Churn {
+    fn add(&mut self, machine_index: u32, stats: &ClassifyStats) {
+        self.machines += 1;
+        self.requeues += stats.requeues;
+        self.revisited_contexts += stats.revisited_contexts;
+        if self
+            .worst
+            .is_none_or(|(requeues, _)| stats.requeues > requeues)
+        {
+            self.worst = Some((stats.requeues, machine_index));
+        }
+    }
 
-        let elapsed = start_time.elapsed();
-        println!("Elapsed: {:.2?}", elapsed);
+    fn print(&self) {
+        let Some((worst_requeues, worst_index)) = self.worst else {
+            return;
+        };
+        println!(
+            "saturation churn: {:.1} requeues and {:.1} revisited contexts per machine, at most {worst_requeues} requeues (machine {worst_index})",
+            self.requeues as f64 / self.machines as f64,
+            self.revisited_contexts as f64 / self.machines as f64
+        );
+    }
+}
+
+fn print_decision(machine: &str, outcome: ClassifyOutcome) {
+    match outcome {
+        Ok(LoopsForever) => {
+            println!("{} loops forever", machine);
+        }
+        Err(reason) => {
+            println!("{} may halt ({})", machine, reason);
+        }
+    }
+}
+
+fn dump_cps(program: &Program, reachable: &PartialReachable) {
+    let radius = reachable.radius();
+    let stats = reachable.stats(program);
+    println!(
+        "radius {}, {} reachable contexts, {:.3e} of the {} possible",
+        radius,
+        stats.reachable_contexts,
+        stats.context_ratio(),
+        stats.possible_contexts
+    );
+    println!(
+        "ngrams added by saturation: left {}, right {}",
+        stats.left_ngram_adds, stats.right_ngram_adds
+    );
+    println!(
+        "contexts queued again by saturation: {} times, {} distinct",
+        stats.requeues, stats.revisited_contexts
+    );
+    println!("contexts by state:");
+    for (state, count) in reachable.contexts_by_state() {
+        println!(" - {state}: {count}");
+    }
+    for (name, dir) in [("left", Dir::Left), ("right", Dir::Right)] {
+        let ngrams: Vec<String> = reachable
+            .ngrams(dir)
+            .into_iter()
+            .map(|ngram| ngram.to_bits_string(radius.side(dir)))
+            .collect();
+        println!("{name} ngrams: {}", ngrams.join(" "));
+    }
+    println!("contexts:");
+    for local_context in reachable.local_contexts() {
+        println!(" - {}", local_context.display(radius));
+    }
+}
+
+fn classify_machine(args: &ClassifyArgs) -> Result<(), i32> {
+    let machine = match Program::try_from_string(&args.machine, args.parse.mode()) {
+        Ok(machine) => machine,
+        Err(err) => {
+            eprintln!("{}: {err}", args.machine);
+            return Err(1);
+        }
+    };
+    let decider = &args.decider;
+    let config = decider.config();
+    if let Some(max_radius) = args.parallel_radius {
+        match classify_min_radius(&machine, max_radius, &config) {
+            Some(radius) => println!("{} loops forever (smallest radius {radius})", args.machine),
+            None => println!(
+                "{} may halt (at every radius up to {max_radius})",
+                args.machine
+            ),
+        }
+    } else if !decider.radii.is_empty() {
+        print_spectrum_header(&decider.radii);
+        let outcomes = classify_multi(&machine, &decider.radii, &config);
+        print_spectrum_row(&args.machine, &outcomes);
+    } else if args.dump_cps {
+        let (outcome, reachable) = classify_retaining(&machine, &config);
+        print_decision(&args.machine, outcome);
+        // The structure was built for the machine as classified, with --treat-as-halt applied.
+        let classified = machine.with_halting_states(&config.treat_as_halt);
+        dump_cps(&classified, &reachable);
+    } else if args.path_to_halt {
+        let (outcome, reachable) = classify_retaining(&machine, &config);
+        print_decision(&args.machine, outcome);
+        // The set was built for the machine as classified, with any --treat-as-halt applied.
+        let classified = machine.with_halting_states(&config.treat_as_halt);
+        if let Some(path) = reachable.path_to_halt(&classified) {
+            println!("path to a halting context:");
+            for local_context in path {
+                println!(" - {}", local_context.display(reachable.radius()));
+            }
+        }
+    } else if args.show_progress && !args.output.quiet {
+        let outcome = classify_with_callback(&machine, &config, |reachable_contexts| {
+            eprint!("\r{reachable_contexts} reachable contexts");
+        });
+        eprintln!();
+        print_decision(&args.machine, outcome);
     } else {
-        match classify_fn(
-            &Program::from_string(&args.machine),
-            args.radius,
-            args.max_context_count,
-        ) {
-            Ok(LoopsForever) => {
-                println!("{} loops forever", args.machine);
+        print_decision(&args.machine, classify_with_config(&machine, &config));
+    }
+    Ok(())
+}
+
+fn classify_stdin(args: &StdinArgs) -> Result<(), i32> {
+    let decider = &args.decider;
+    let config = decider.config();
+    if !decider.radii.is_empty() {
+        print_spectrum_header(&decider.radii);
+    }
+    for line in std::io::stdin().lines() {
+        let line = line.expect("can read stdin");
+        let machine_text = line.trim();
+        if machine_text.is_empty() {
+            continue;
+        }
+        let machine = match Program::try_from_string(machine_text, args.parse.mode()) {
+            Ok(machine) => machine,
+            Err(err) => {
+                eprintln!("{machine_text}: {err}");
+                return Err(1);
             }
-            Err(MayHalt) => {
-                println!("{} may halt", args.machine);
+        };
+        if !decider.radii.is_empty() {
+            let outcomes = classify_multi(&machine, &decider.radii, &config);
+            print_spectrum_row(machine_text, &outcomes);
+        } else {
+            print_decision(machine_text, classify_with_config(&machine, &config));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Reads an index file of 4-byte big-endian machine indices, sorted ascending without duplicates.
+ */
+fn read_index_sorted(path: &std::path::Path) -> Result<Vec<u32>, String> {
+    let bytes =
+        std::fs::read(path).map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+    if !bytes.len().is_multiple_of(4) {
+        return Err(format!(
+            "{} is {} bytes long, which is not a whole number of 4-byte machine indices",
+            path.display(),
+            bytes.len()
+        ));
+    }
+    let mut machine_indices: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|entry| u32::from_be_bytes(entry.try_into().expect("entries are 4 bytes")))
+        .collect();
+    machine_indices.sort_unstable();
+    machine_indices.dedup();
+    Ok(machine_indices)
+}
+
+fn diff_indices(args: &DiffArgs) -> Result<(), i32> {
+    let read = |path: &std::path::Path| {
+        read_index_sorted(path).map_err(|message| {
+            eprintln!("{message}");
+            1
+        })
+    };
+    let left = read(&args.left)?;
+    let right = read(&args.right)?;
+
+    // Merge the two sorted lists, tagging each index with where it was found.
+    let mut categorized = Vec::with_capacity(left.len().max(right.len()));
+    let (mut l, mut r) = (0, 0);
+    loop {
+        let entry = match (left.get(l), right.get(r)) {
+            (Some(&a), Some(&b)) => match a.cmp(&b) {
+                std::cmp::Ordering::Less => ("left-only", a),
+                std::cmp::Ordering::Greater => ("right-only", b),
+                std::cmp::Ordering::Equal => ("both", a),
+            },
+            (Some(&a), None) => ("left-only", a),
+            (None, Some(&b)) => ("right-only", b),
+            (None, None) => break,
+        };
+        let (category, _) = entry;
+        l += usize::from(category != "right-only");
+        r += usize::from(category != "left-only");
+        categorized.push(entry);
+    }
+
+    for category in ["left-only", "right-only", "both"] {
+        let count = categorized.iter().filter(|(c, _)| *c == category).count();
+        println!("{category:<11} {count:>10}");
+    }
+    if args.list {
+        for (category, machine_index) in categorized {
+            println!("{category} {machine_index}");
+        }
+    }
+    Ok(())
+}
+
+fn canonicalize(args: &CanonicalizeArgs) -> Result<(), i32> {
+    let format = if args.packed {
+        MachineFormat::Packed
+    } else {
+        MachineFormat::Separated
+    };
+    let print_canonical = |machine_text: &str| -> Result<(), i32> {
+        let machine = Program::try_from_string(machine_text, args.parse.mode()).map_err(|err| {
+            eprintln!("{machine_text}: {err}");
+            1
+        })?;
+        let machine = if args.tnf {
+            machine.to_tnf(args.tnf_steps)
+        } else {
+            machine
+        };
+        println!("{}", machine.to_string_in(format));
+        Ok(())
+    };
+
+    if let Some(machine_text) = &args.machine {
+        return print_canonical(machine_text.trim());
+    }
+    for line in std::io::stdin().lines() {
+        let line = line.expect("can read stdin");
+        let machine_text = line.trim();
+        if !machine_text.is_empty() {
+            print_canonical(machine_text)?;
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Fills in the `{radius}` and `{max_context_count}` placeholders of an output file name.
+ * An asymmetric radius is written with both sides, like `3l5r`, so it never collides with
+ * a symmetric one.
+ */
+fn output_name(template: &str, config: &ClassifyConfig) -> String {
+    let radius = config
+        .effective_radius()
+        .expect("the radius is validated while parsing the command line");
+    template
+        .replace("{radius}", &radius.to_string())
+        .replace("{max_context_count}", &config.max_context_count.to_string())
+}
+
+fn run_db(args: &RunDbArgs) -> Result<(), i32> {
+    let start_time = Instant::now();
+    let decider = &args.decider;
+    let config = decider.config();
+    let sample = Sample::from_args(args);
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.threads)
+        .build_global()
+        .expect("can build the global thread pool");
+
+    if !args.chain && !decider.radii.is_empty() {
+        // These only apply to a run writing index files, which a --radii or --frontier table is not.
+        let index_only = [
+            ("--cross-check", args.cross_check),
+            ("--bbchallenge-output", args.bbchallenge_output.is_some()),
+            ("--bitmap-output", args.bitmap_output.is_some()),
+        ];
+        if let Some((name, _)) = index_only.iter().find(|(_, given)| *given) {
+            eprintln!("{name} does not apply to a --radii table");
+            return Err(1);
+        }
+    }
+    if args.chain {
+        check_chain_names(args)?;
+        with_manifest(args, |manifest| {
+            run_db_chain(args, &config, sample, manifest)
+        })?;
+    } else if args.frontier {
+        run_db_frontier(args, &config, sample)?;
+    } else if !decider.radii.is_empty() {
+        print_spectrum_header(&decider.radii);
+        let read = for_each_database_chunk(
+            &args.seed_database,
+            &args.undecided_index,
+            !args.no_mmap,
+            args.parse.mode(),
+            sample,
+            |chunk| {
+                let outcomes: Vec<Vec<ClassifyOutcome>> = chunk
+                    .par_iter()
+                    .map(|(_, machine)| classify_multi(machine, &decider.radii, &config))
+                    .collect();
+                for ((machine_index, _), outcomes) in chunk.iter().zip(outcomes) {
+                    print_spectrum_row(&format!("{machine_index}"), &outcomes);
+                }
+            },
+        );
+        if let Err(message) = read {
+            eprintln!("{message}");
+            return Err(1);
+        }
+        return Ok(());
+    } else {
+        with_manifest(args, |manifest| {
+            let mut looping_indices = Vec::new();
+            let mut ndjson = args.ndjson.as_deref().map(NdjsonOutput::create);
+            let pass = run_db_pass(
+                args,
+                &config,
+                &args.undecided_index,
+                sample,
+                &mut looping_indices,
+                ndjson.as_mut(),
+            )?;
+            manifest.add_pass(&pass);
+            write_looping_outputs(args, looping_indices)
+        })?;
+    }
+
+    if !args.output.quiet {
+        println!("Elapsed: {:.2?}", start_time.elapsed());
+    }
+    Ok(())
+}
+
+/**
+ * Whether both paths name the same existing file.
+ */
+fn is_same_file(a: &std::path::Path, b: &std::path::Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/**
+ * Fails unless the `--chain` radii strictly increase and every output file name has a
+ * `{radius}` placeholder. Otherwise two passes would write the same file, and reading the
+ * previous pass's undecided index while creating it again would empty it.
+ */
+fn check_chain_names(args: &RunDbArgs) -> Result<(), i32> {
+    let radii = &args.decider.radii;
+    if let Some(&[radius, next]) = radii.windows(2).find(|pair| pair[0] >= pair[1]) {
+        eprintln!("--chain needs strictly increasing --radii, but {radius} is followed by {next}");
+        return Err(1);
+    }
+    let looping_name = (!args.undecided_only).then_some(("--looping-name", &args.looping_name));
+    for (option, name) in looping_name
+        .into_iter()
+        .chain([("--undecided-name", &args.undecided_name)])
+    {
+        if !name.contains("{radius}") {
+            eprintln!("{option} {name} has no {{radius}} placeholder, so the passes of --chain would overwrite each other's output");
+            return Err(1);
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Runs `--radii` one after the other, each on the machines the previous radius left undecided,
+ * so each radius's looping index holds exactly the machines first decided at that radius.
+ */
+fn run_db_chain(
+    args: &RunDbArgs,
+    config: &ClassifyConfig,
+    mut sample: Option<Sample>,
+    manifest: &mut Manifest,
+) -> Result<(), i32> {
+    let mut undecided_index = args.undecided_index.clone();
+    let mut looping_indices = Vec::new();
+    let mut ndjson = args.ndjson.as_deref().map(NdjsonOutput::create);
+    let mut decided_at = Vec::new();
+    for &radius in &args.decider.radii {
+        println!("radius {radius}:");
+        let config = config.clone().with_radius(radius);
+        // Only the first radius samples; the later ones take all the sample left undecided.
+        let pass = run_db_pass(
+            args,
+            &config,
+            &undecided_index,
+            sample.take(),
+            &mut looping_indices,
+            ndjson.as_mut(),
+        )?;
+        manifest.add_pass(&pass);
+        decided_at.push((radius, pass.count_loops, pass.count_undecided));
+        undecided_index = vec![pass.undecided_path.to_string_lossy().into_owned()];
+    }
+    write_looping_outputs(args, looping_indices)?;
+
+    println!("chain:");
+    println!("{:<8} {:>10} {:>10}", "radius", "decided", "undecided");
+    for (radius, count_loops, count_undecided) in decided_at {
+        println!("{radius:<8} {count_loops:>10} {count_undecided:>10}");
+    }
+    Ok(())
+}
+
+/**
+ * Counts, for each of `--radii`, the machines that radius decides but the radius just below it
+ * does not. Unlike `--chain`, which credits a machine to the first of `--radii` deciding it,
+ * this compares with the radius below even when that is not one of `--radii`.
+ */
+fn run_db_frontier(
+    args: &RunDbArgs,
+    config: &ClassifyConfig,
+    sample: Option<Sample>,
+) -> Result<(), i32> {
+    let radii = &args.decider.radii;
+    let mut decided_exactly = vec![0; radii.len()];
+    let mut count_processed = 0;
+    let read = for_each_database_chunk(
+        &args.seed_database,
+        &args.undecided_index,
+        !args.no_mmap,
+        args.parse.mode(),
+        sample,
+        |chunk| {
+            let decided: Vec<Vec<bool>> = chunk
+                .par_iter()
+                .map(|(_, machine)| {
+                    radii
+                        .iter()
+                        .map(|&radius| decided_exactly_at_with_config(machine, radius, config))
+                        .collect()
+                })
+                .collect();
+            count_processed += chunk.len();
+            for machine_decided in decided {
+                for (count, decided) in decided_exactly.iter_mut().zip(machine_decided) {
+                    *count += usize::from(decided);
+                }
             }
+        },
+    );
+    if let Err(message) = read {
+        eprintln!("{message}");
+        return Err(1);
+    }
+
+    println!("frontier over {count_processed} machines:");
+    println!("{:<8} {:>16}", "radius", "decided exactly");
+    for (radius, count) in radii.iter().zip(decided_exactly) {
+        println!("{radius:<8} {count:>16}");
+    }
+    Ok(())
+}
+
+fn write_looping_outputs(args: &RunDbArgs, looping: Vec<u32>) -> Result<(), i32> {
+    if let Some(path) = &args.bitmap_output {
+        let written = SeedDatabase::open(&args.seed_database, !args.no_mmap)
+            .map_err(|err| err.to_string())
+            .and_then(|database| {
+                write_bitmap(path, database.len(), &looping).map_err(|err| err.to_string())
+            });
+        if let Err(err) = written {
+            eprintln!("cannot write --bitmap-output {}: {err}", path.display());
+            return Err(1);
+        }
+    }
+    if let Some(path) = &args.bbchallenge_output {
+        if let Err(err) = write_bbchallenge_index(path, looping) {
+            eprintln!(
+                "cannot write --bbchallenge-output {}: {err}",
+                path.display()
+            );
+            return Err(1);
         }
     }
     Ok(())
 }
+
+/**
+ * Estimates the looping share of all `entry_count` machines from the `count_loops` of the
+ * `count_processed` sampled ones, with a 95% confidence interval from the normal approximation
+ * (corrected for sampling without replacement).
+ */
+fn print_sample_estimate(
+    sample: Sample,
+    count_loops: usize,
+    count_processed: usize,
+    entry_count: usize,
+) {
+    if count_processed == 0 {
+        return;
+    }
+    let share = count_loops as f64 / count_processed as f64;
+    let finite_population = if entry_count > 1 {
+        (entry_count - count_processed) as f64 / (entry_count - 1) as f64
+    } else {
+        0.0
+    };
+    let margin = 1.96 * (share * (1.0 - share) / count_processed as f64 * finite_population).sqrt();
+    println!(
+        " - sampled {count_processed} of {entry_count} machines (--seed {}): an estimated {:.2}% loop, +/- {:.2}% (95% confidence)",
+        sample.seed,
+        share * 100.0,
+        margin * 100.0
+    );
+}
+
+/**
+ * Prints how the time spent on each machine splits between parsing it, saturating its reachable
+ * structure and checking that structure's closure, summed over every machine of a pass. The
+ * machines are classified on all threads at once, so the total is CPU time, not wall time.
+ */
+fn print_phase_times(parse_time: Duration, saturate_time: Duration, check_time: Duration) {
+    let total = parse_time + saturate_time + check_time;
+    if total.is_zero() {
+        return;
+    }
+    let share = |time: Duration| time.as_secs_f64() * 100.0 / total.as_secs_f64();
+    println!(
+        "time: parse {:.0}%, saturate {:.0}%, check {:.0}% (of {:.2?})",
+        share(parse_time),
+        share(saturate_time),
+        share(check_time),
+        total
+    );
+}
+
+/**
+ * The outcome of classifying every machine of a run at one radius.
+ */
+struct PassSummary {
+    radius: Radius,
+    count_processed: usize,
+    count_loops: usize,
+    count_undecided: usize,
+    looping_path: Option<std::path::PathBuf>,
+    undecided_path: std::path::PathBuf,
+}
+
+/**
+ * Classifies the machines of `undecided_index` (or just a `sample` of them) with `config`,
+ * writing the output index files and printing a summary. The looping machines are also added
+ * to `looping_indices`, if `--bbchallenge-output` or `--bitmap-output` was given, and every
+ * machine to `ndjson`, if given.
+ */
+fn run_db_pass(
+    args: &RunDbArgs,
+    config: &ClassifyConfig,
+    undecided_index: &[String],
+    sample: Option<Sample>,
+    looping_indices: &mut Vec<u32>,
+    mut ndjson: Option<&mut NdjsonOutput>,
+) -> Result<PassSummary, i32> {
+    // With --undecided-only, the looping machines are only counted, so that the single output
+    // file can be fed straight back in as the --undecided-index of a larger radius.
+    std::fs::create_dir_all(&args.output_dir).expect("can create --output-dir");
+    let output_path =
+        |name_template: &str| args.output_dir.join(output_name(name_template, config));
+    let looping_path = (!args.undecided_only).then(|| output_path(&args.looping_name));
+    let undecided_path = output_path(&args.undecided_name);
+    // Creating an output truncates it, so it must not be one of the inputs still to be read.
+    for output in looping_path.iter().chain([&undecided_path]) {
+        if let Some(input) = undecided_index
+            .iter()
+            .find(|input| is_same_file(std::path::Path::new(input), output))
+        {
+            eprintln!(
+                "{} would overwrite the --undecided-index {input} it is read from; choose another --output-dir or file name",
+                output.display()
+            );
+            return Err(1);
+        }
+    }
+    let mut output_file_looping = looping_path
+        .as_ref()
+        .map(|looping_path| IndexOutput::create(looping_path, args.sort_output));
+    let mut output_file_halting = IndexOutput::create(&undecided_path, args.sort_output);
+
+    let mut count_processed = 0;
+    let mut count_loops = 0;
+    let mut count_undecided = 0;
+    let mut count_budget_limited = 0;
+    let mut count_halted_in_presim = 0;
+    let mut count_stuck_at_start = 0;
+    let mut context_size_histogram = ContextSizeHistogram::default();
+    let mut ngram_tally = NGramTally::default();
+    let mut cross_check = CrossCheck::default();
+    let mut radius_use = RadiusUse::default();
+    let mut churn = Churn::default();
+    let mut transition_use = TransitionUse::default();
+    let mut saturate_time = Duration::ZERO;
+    let mut check_time = Duration::ZERO;
+
+    let read = for_each_database_chunk(
+        &args.seed_database,
+        undecided_index,
+        !args.no_mmap,
+        args.parse.mode(),
+        sample,
+        |chunk| {
+            // Classify out of order, but collect (and so write) in input order.
+            let outcomes: Vec<_> = chunk
+                .par_iter()
+                .map(|(_, machine)| {
+                    let cycler = args.cross_check.then(|| {
+                        decide_cycler(
+                            &cross_check_program(machine, config),
+                            CROSS_CHECK_MAX_STEPS,
+                            CROSS_CHECK_MAX_WINDOW,
+                        )
+                    });
+                    let classify_start = Instant::now();
+                    let classified = classify_with_stats(machine, config);
+                    (classified, classify_start.elapsed(), cycler)
+                })
+                .collect();
+            for ((machine_index, machine), ((outcome, stats), elapsed, cycler)) in
+                chunk.iter().zip(outcomes)
+            {
+                count_processed += 1;
+                saturate_time += stats.saturate_time;
+                check_time += stats.check_time;
+                if let Some(ndjson) = &mut ndjson {
+                    ndjson.push(
+                        *machine_index,
+                        machine,
+                        stats.radius,
+                        outcome,
+                        &stats,
+                        elapsed,
+                    );
+                }
+                context_size_histogram.add(stats.reachable_contexts, outcome);
+                ngram_tally.add(&stats);
+                transition_use.add(machine, &stats, outcome);
+                churn.add(*machine_index, &stats);
+                if let Some(cycler) = cycler {
+                    cross_check.add(*machine_index, outcome, cycler);
+                }
+                match outcome {
+                    Ok(LoopsForever) => {
+                        count_loops += 1;
+                        radius_use.add(&stats);
+                        if args.bbchallenge_output.is_some() || args.bitmap_output.is_some() {
+                            looping_indices.push(*machine_index);
+                        }
+                        if let Some(output_file_looping) = &mut output_file_looping {
+                            output_file_looping.push(*machine_index);
+                        }
+                    }
+                    Err(reason) => {
+                        count_undecided += 1;
+                        if reason.is_budget_limited() {
+                            count_budget_limited += 1;
+                        }
+                        if let MayHalt::HaltedInPresim { .. } = reason {
+                            count_halted_in_presim += 1;
+                        }
+                        if reason == MayHalt::StuckAtStart {
+                            count_stuck_at_start += 1;
+                        }
+                        output_file_halting.push(*machine_index);
+                    }
+                }
+
+                if count_processed % 100 == 0 {
+                    // Flush first, so that everything counted so far is also on disk.
+                    if let Some(output_file_looping) = &mut output_file_looping {
+                        output_file_looping.flush();
+                    }
+                    output_file_halting.flush();
+                    if let Some(ndjson) = &mut ndjson {
+                        ndjson.flush();
+                    }
+                    if !args.output.quiet {
+                        println!(
+                            "processed {} :: {}% are looping",
+                            count_processed,
+                            count_loops * 100 / count_processed
+                        );
+                    }
+                }
+            }
+        },
+    );
+    if let Some(output_file_looping) = &mut output_file_looping {
+        output_file_looping.finish();
+    }
+    output_file_halting.finish();
+    if let Some(ndjson) = &mut ndjson {
+        ndjson.flush();
+    }
+    let read = match read {
+        Ok(read) => read,
+        Err(message) => {
+            eprintln!("{message}");
+            return Err(1);
+        }
+    };
+
+    println!("done");
+    println!(" - total:      {count_processed:>8}");
+    println!(" - loops:      {count_loops:>8}");
+    println!(" - undecided:  {count_undecided:>8}");
+    println!("   - budget-limited: {count_budget_limited:>8} (a larger budget may help)");
+    println!(
+        "   - structural:     {:>8} (only another radius may help)",
+        count_undecided - count_budget_limited
+    );
+    if let Some(presim_steps) = config.presim_steps {
+        println!(
+            "     - halted:       {count_halted_in_presim:>8} (the others run for over {presim_steps} steps)"
+        );
+    }
+    if count_stuck_at_start > 0 {
+        println!(
+            "     - stuck at start: {count_stuck_at_start:>6} (the start transition halts, so check the input)"
+        );
+    }
+    if let Some(sample) = sample {
+        print_sample_estimate(sample, count_loops, count_processed, read.entry_count);
+    }
+    print_phase_times(read.parse_time, saturate_time, check_time);
+    context_size_histogram.print();
+    ngram_tally.print();
+    transition_use.print();
+    radius_use.print();
+    churn.print();
+    if args.cross_check {
+        cross_check.print();
+    }
+
+    Ok(PassSummary {
+        radius: config
+            .effective_radius()
+            .unwrap_or_else(|err| panic!("{err}")),
+        count_processed,
+        count_loops,
+        count_undecided,
+        looping_path,
+        undecided_path,
+    })
+}
+
+fn main() -> Result<(), i32> {
+    let args = Args::parse();
+    let output = match &args.command {
+        Command::Classify(args) => Some(&args.output),
+        Command::RunDb(args) => Some(&args.output),
+        Command::Stdin(args) => Some(&args.output),
+        // Canonicalized machines are meant to be piped elsewhere, so keep its output clean.
+        Command::Canonicalize(_) | Command::Diff(_) => None,
+    };
+    if output.is_some_and(|output| output.verbose > 0) {
+        println!("args: {:?}", args);
+    }
+
+    match &args.command {
+        Command::Classify(args) => classify_machine(args),
+        Command::RunDb(args) => run_db(args),
+        Command::Stdin(args) => classify_stdin(args),
+        Command::Canonicalize(args) => canonicalize(args),
+        Command::Diff(args) => diff_indices(args),
+    }
+}
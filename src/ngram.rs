@@ -1,60 +1,271 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, VecDeque};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
 
-use crate::program::{Bit, Dir, LoopsForever, MayHalt, Program, State};
+use rayon::prelude::*;
+
+use crate::program::{Bit, Dir, LoopsForever, MayHalt, ParseError, ParseMode, Program, State};
+use crate::reachable_set::{ContextSet, ReachableSet};
+use crate::simulate::steps_to_halt;
 
 /**
 * n-grams may go up to 15 bits.
 * (32 bits is not allowed because of the context size)
 */
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, PartialOrd, Ord)]
-struct NGram(NGramBits);
-type NGramBits = u32;
+pub struct NGram(pub NGramBits);
+pub type NGramBits = u32;
 
+/**
+ * The state of the machine together with the bits on either side of the head, `left` of them
+ * on the left and `right` on the right for a `Radius` with those sides.
+ * Bit `i` of `nearby_bits` is the `i`th cell of the window, from left to right, so the left
+ * ngram is bits `[0, left)`, the head is bit `left` and the right ngram is bits
+ * `(left, left + right]`.
+ */
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, PartialOrd, Ord)]
-struct LocalContext {
-    state: State,
-    nearby_bits: u64,
+pub struct LocalContext {
+    pub state: State,
+    pub nearby_bits: u64,
+}
+
+/**
+ * How many cells on each side of the head a local context covers, each always in [1, 31]
+ * (a context's `left + right + 1` bits must fit in a `u64`, and each side in an `NGramBits`).
+ * Usually both sides are the same, but a machine drifting one way may only need a large
+ * radius on one side.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Debug, PartialOrd, Ord)]
+pub struct Radius {
+    left: u8,
+    right: u8,
+}
+
+impl Radius {
+    pub const MIN: u8 = 1;
+    pub const MAX: u8 = 31;
+
+    /**
+     * The same radius on both sides. Returns `None` unless `radius` lies in
+     * [`Radius::MIN`, `Radius::MAX`].
+     */
+    pub fn new(radius: u8) -> Option<Radius> {
+        Radius::asymmetric(radius, radius)
+    }
+
+    /**
+     * Returns `None` unless both sides lie in [`Radius::MIN`, `Radius::MAX`].
+     */
+    pub fn asymmetric(left: u8, right: u8) -> Option<Radius> {
+        let valid = |side| (Radius::MIN..=Radius::MAX).contains(&side);
+        (valid(left) && valid(right)).then_some(Radius { left, right })
+    }
+
+    /**
+     * The radius of a symmetric `Radius`, or the larger side of an asymmetric one.
+     */
+    pub fn get(self) -> u8 {
+        self.left.max(self.right)
+    }
+
+    pub fn left(self) -> u8 {
+        self.left
+    }
+
+    pub fn right(self) -> u8 {
+        self.right
+    }
+
+    pub fn side(self, dir: Dir) -> u8 {
+        match dir {
+            Dir::Left => self.left,
+            Dir::Right => self.right,
+        }
+    }
+
+    pub fn is_symmetric(self) -> bool {
+        self.left == self.right
+    }
+
+    /**
+     * The number of cells a local context covers.
+     */
+    pub(crate) fn width(self) -> u8 {
+        self.left + self.right + 1
+    }
+}
+
+// A whole context, at the largest radius on both sides, must fit in the bits of a `u64`, and
+// each of its ngrams in an `NGramBits`, so that none of the shifts below can overflow.
+const _: () = assert!(2 * Radius::MAX as u32 + 1 < u64::BITS);
+const _: () = assert!((Radius::MAX as u32) < NGramBits::BITS);
+
+/**
+ * Just bit `i` of a context's window set. `i` is at most `Radius::width() - 1` for the
+ * positions of a window, or `width()` for the bit just past it.
+ */
+fn window_bit(i: u8) -> u64 {
+    debug_assert!(u32::from(i) < u64::BITS, "shift by {i} overflows a u64");
+    1 << i
 }
 
+/**
+ * The lowest `n` bits set, for `n` up to `Radius::MAX`: the mask of an ngram with `n` bits.
+ */
+fn low_bits(n: u8) -> u64 {
+    debug_assert!(n <= Radius::MAX, "mask of {n} bits is wider than an ngram");
+    window_bit(n) - 1
+}
+
+/**
+ * The context with the head in state A, reading `blank` with only `blank`s around it.
+ */
+fn start_context(radius: Radius, blank: Bit) -> LocalContext {
+    LocalContext::from_parts(
+        State(1),
+        blank_ngram(radius.left, blank),
+        blank,
+        blank_ngram(radius.right, blank),
+        radius,
+    )
+}
+
+/**
+ * The ngram of `len` cells that are all `blank`.
+ */
+fn blank_ngram(len: u8, blank: Bit) -> NGram {
+    NGram(if blank.0 {
+        low_bits(len) as NGramBits
+    } else {
+        0
+    })
+}
+
+impl Default for Radius {
+    fn default() -> Self {
+        Radius {
+            left: Radius::MIN,
+            right: Radius::MIN,
+        }
+    }
+}
+
+/**
+ * A symmetric radius as just its number, e.g. `4`, and an asymmetric one as both sides,
+ * e.g. `3l5r` for 3 cells on the left and 5 on the right.
+ */
+impl std::fmt::Display for Radius {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_symmetric() {
+            f.pad(&self.left.to_string())
+        } else {
+            f.pad(&format!("{}l{}r", self.left, self.right))
+        }
+    }
+}
+
+/**
+ * A radius outside [`Radius::MIN`, `Radius::MAX`].
+ */
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
-struct Radius(u8);
+pub struct InvalidRadius(pub u8);
+
+impl std::fmt::Display for InvalidRadius {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "radius {} is out of range, it must lie in [{}, {}]",
+            self.0,
+            Radius::MIN,
+            Radius::MAX
+        )
+    }
+}
+
+impl std::error::Error for InvalidRadius {}
+
+/**
+ * The same radius on both sides, panicking unless it lies in [`Radius::MIN`, `Radius::MAX`].
+ * Use `Radius::new` to check it instead.
+ */
+impl From<u8> for Radius {
+    fn from(radius: u8) -> Radius {
+        Radius::new(radius).unwrap_or_else(|| panic!("{}", InvalidRadius(radius)))
+    }
+}
 
 impl LocalContext {
+    pub fn new(state: State, nearby_bits: u64) -> Self {
+        LocalContext { state, nearby_bits }
+    }
+    /**
+     * Assembles the context with the head in `state` reading `center`, with `left` and `right`
+     * on either side. Each ngram must fit in its side of `radius`.
+     */
+    pub fn from_parts(
+        state: State,
+        left: NGram,
+        center: Bit,
+        right: NGram,
+        radius: Radius,
+    ) -> Self {
+        debug_assert!(u64::from(left.0) & !low_bits(radius.left) == 0);
+        debug_assert!(u64::from(right.0) & !low_bits(radius.right) == 0);
+        LocalContext {
+            state,
+            nearby_bits: u64::from(left.0)
+                | u64::from(center.0) << radius.left
+                | u64::from(right.0) << (radius.left + 1),
+        }
+    }
     fn push_left(self, bit: Bit, radius: Radius) -> Self {
         LocalContext {
             state: self.state,
-            nearby_bits: (self.nearby_bits << 1 | u64::from(bit.0)) & !(1 << (radius.0 * 2 + 1)),
+            nearby_bits: (self.nearby_bits << 1 | u64::from(bit.0)) & !window_bit(radius.width()),
         }
     }
     fn push_right(self, bit: Bit, radius: Radius) -> Self {
         LocalContext {
             state: self.state,
-            nearby_bits: self.nearby_bits >> 1 | (if bit.0 { 1 << (2 * radius.0) } else { 0 }),
+            nearby_bits: self.nearby_bits >> 1
+                | (if bit.0 {
+                    window_bit(radius.width() - 1)
+                } else {
+                    0
+                }),
         }
     }
     fn push(self, dir: Dir, bit: Bit, radius: Radius) -> Self {
-        match dir {
+        let pushed = match dir {
             Dir::Left => self.push_left(bit, radius),
             Dir::Right => self.push_right(bit, radius),
-        }
+        };
+        // Stray bits past the window would make equal contexts compare as distinct.
+        debug_assert!(
+            pushed.nearby_bits >> radius.width() == 0,
+            "context bits {:#x} overflow the window at radius {radius}",
+            pushed.nearby_bits
+        );
+        pushed
     }
     fn write_center(self, bit: Bit, state: State, radius: Radius) -> Self {
         LocalContext {
             state,
-            nearby_bits: (self.nearby_bits & !(1 << radius.0))
-                | (if bit.0 { 1 << radius.0 } else { 0 }),
+            nearby_bits: (self.nearby_bits & !window_bit(radius.left))
+                | (if bit.0 { window_bit(radius.left) } else { 0 }),
         }
     }
-    fn get_center(self, radius: Radius) -> Bit {
-        Bit((self.nearby_bits & (1 << radius.0)) != 0)
+    pub fn get_center(self, radius: Radius) -> Bit {
+        Bit((self.nearby_bits & window_bit(radius.left)) != 0)
     }
-    fn get_left(self, radius: Radius) -> NGram {
-        NGram((self.nearby_bits & ((1 << radius.0) - 1)) as NGramBits)
+    pub fn get_left(self, radius: Radius) -> NGram {
+        NGram((self.nearby_bits & low_bits(radius.left)) as NGramBits)
     }
-    fn get_right(self, radius: Radius) -> NGram {
-        NGram(((self.nearby_bits >> (radius.0 + 1)) & ((1 << radius.0) - 1)) as NGramBits)
+    pub fn get_right(self, radius: Radius) -> NGram {
+        NGram(((self.nearby_bits >> (radius.left + 1)) & low_bits(radius.right)) as NGramBits)
     }
-    fn get(self, dir: Dir, radius: Radius) -> NGram {
+    pub fn get(self, dir: Dir, radius: Radius) -> NGram {
         match dir {
             Dir::Left => self.get_left(radius),
             Dir::Right => self.get_right(radius),
@@ -62,6 +273,58 @@ impl LocalContext {
     }
 }
 
+/**
+ * The result of applying one program step to a local context.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum StepResult {
+    /**
+     * The transition for the context's center bit and state is undefined (or goes to `State::HALT`).
+     */
+    Halt,
+    /**
+     * The head moved in direction `dir`, so the ngram `falling_off` dropped off the opposite side
+     * of the window. A new bit is pulled in on the `dir` side, which could be either a 0 or a 1;
+     * `successors[b]` is the resulting context if it is `b`.
+     */
+    Step {
+        dir: Dir,
+        falling_off: NGram,
+        successors: [LocalContext; 2],
+    },
+}
+
+/**
+ * Applies one step of `program` to the local context `ctx`.
+ * This says nothing about whether the successors are actually reachable; that depends on
+ * whether the ngram pulled in on the `dir` side is.
+ */
+pub fn step_context(program: &Program, ctx: LocalContext, radius: Radius) -> StepResult {
+    // For this local context, see what the program says to do.
+    let action = match program.action(ctx.get_center(radius), ctx.state) {
+        Ok(action) => action,
+        Err(_) => return StepResult::Halt,
+    };
+
+    // Suppose the action says to move left. This is the naming convention we use:
+    let dir = action.2;
+
+    // Since we are moving "left", the opposite side (right) must have an ngram "fall off" of the local context.
+    let falling_off = ctx.get(dir.opposite(), radius);
+
+    // A single step causes us to write the center bit, and then push a new bit onto the left.
+    // We don't know what that bit is, just that it's either 0 or 1, so both cases are returned.
+    let written = ctx.write_center(action.1, action.0, radius);
+    StepResult::Step {
+        dir,
+        falling_off,
+        successors: [
+            written.push(dir, Bit(false), radius),
+            written.push(dir, Bit(true), radius),
+        ],
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, PartialOrd, Ord)]
 struct DirMap<T> {
     left: T,
@@ -98,158 +361,497 @@ impl<T> std::ops::IndexMut<Dir> for DirMap<T> {
     }
 }
 
-struct PartialReachable {
-    radius: Radius, // must lie in [1, 31]
-    reachable_local_contexts: BTreeSet<LocalContext>,
+/**
+ * What one program step from a reachable context implies: the head moves in direction `dir`,
+ * so `falling_off` must be reachable on the opposite side, and so must every successor whose
+ * pulled-in ngram is reachable.
+ */
+struct Implications {
+    dir: Dir,
+    falling_off: NGram,
+    falling_off_known: bool,
+    successors: [Successor; 2],
+}
+
+enum Successor {
+    /**
+     * The ngram pulled in on the `dir` side is not known to be reachable, so neither is this.
+     */
+    Unsupported(NGram),
+    /**
+     * Reachable in a single step, but not yet in the reachable set.
+     */
+    New(LocalContext),
+    /**
+     * Reachable, and already in the reachable set.
+     */
+    Known,
+}
+
+/**
+ * How many work items saturation processes between calls to its progress callback.
+ */
+const PROGRESS_INTERVAL: usize = 4096;
+
+/**
+ * The set of local contexts and ngrams found to be reachable so far. Once it is closed under a
+ * program step, it covers every configuration the machine can reach from the blank tape.
+ */
+pub struct PartialReachable {
+    radius: Radius,
+    // The symbol every cell of the tape starts out as.
+    blank: Bit,
+    reachable_local_contexts: ContextSet,
     reachable_ngrams: DirMap<BTreeSet<NGram>>,
+    // How many ngrams saturation has added on each side, beyond the initial blank ones.
+    ngram_adds: DirMap<usize>,
+    // How many times saturation queued a context again once an ngram it was waiting on turned
+    // up, and how many distinct contexts that happened to.
+    requeues: usize,
+    revisited_contexts: usize,
+    // How long saturating and then checking the closure took, summed over every radius tried.
+    saturate_time: Duration,
+    check_time: Duration,
 }
 
 impl PartialReachable {
-    fn new(radius: u8) -> Self {
-        if !(1..=31).contains(&radius) {
-            panic!("PartialReachable radius must lie in [1, 31]");
-        }
+    fn new(radius: Radius, blank: Bit) -> Self {
         PartialReachable {
-            radius: Radius(radius),
-            reachable_local_contexts: [LocalContext {
-                state: State(1),
-                nearby_bits: 0,
-            }]
-            .into_iter()
-            .collect(),
-            reachable_ngrams: DirMap::new({
-                let mut res = BTreeSet::new();
-                res.insert(NGram(0));
+            radius,
+            blank,
+            reachable_local_contexts: {
+                let mut res = ContextSet::new(radius);
+                res.insert(start_context(radius, blank));
                 res
-            }),
+            },
+            reachable_ngrams: {
+                let mut res = DirMap::new(BTreeSet::new());
+                res[Dir::Left].insert(blank_ngram(radius.left, blank));
+                res[Dir::Right].insert(blank_ngram(radius.right, blank));
+                res
+            },
+            ngram_adds: DirMap::new(0),
+            requeues: 0,
+            revisited_contexts: 0,
+            saturate_time: Duration::ZERO,
+            check_time: Duration::ZERO,
         }
     }
 
     /**
-     * Checks to see if an extension is needed to capture all reachable states.
-     * If so, returns true and adds some of them.
-     * Call this method repeatedly until false to ensure that we capture all of them.
+     * The symbol the tape starts out filled with.
+     */
+    pub fn blank(&self) -> Bit {
+        self.blank
+    }
+
+    /**
+     * The radius the local contexts were built at.
+     */
+    pub fn radius(&self) -> Radius {
+        self.radius
+    }
+
+    /**
+     * The reachable local contexts, in order.
+     */
+    pub fn local_contexts(&self) -> impl Iterator<Item = LocalContext> + '_ {
+        self.reachable_local_contexts.iter()
+    }
+
+    /**
+     * The ngrams reachable on the `dir` side of the head, in order.
+     */
+    pub fn ngrams(&self, dir: Dir) -> BTreeSet<NGram> {
+        self.reachable_ngrams[dir].iter().copied().collect()
+    }
+
+    /**
+     * Writes the whole structure, so that it can be read back with `read_from`, as big-endian:
+     * the left and right radius as a `u8` each; the blank symbol as a `u8`; the number of local contexts as a `u64`, then each as its state
+     * (a `u8`) and its bits (a `u64`); then for the left and then the right side, the number of
+     * reachable ngrams as a `u64`, then each ngram as a `u32`.
      */
-    fn check_if_closed_under_program_step(&self, program: &Program) -> bool {
+    pub fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&[self.radius.left, self.radius.right, u8::from(self.blank.0)])?;
+        w.write_all(&(self.reachable_local_contexts.len() as u64).to_be_bytes())?;
         for local_context in self.reachable_local_contexts.iter() {
-            // For this local context, see what the program says to do.
-            let action =
-                match program.action(local_context.get_center(self.radius), local_context.state) {
-                    Ok(action) => action,
-                    _ => return false,
+            w.write_all(&[local_context.state.0])?;
+            w.write_all(&local_context.nearby_bits.to_be_bytes())?;
+        }
+        for dir in [Dir::Left, Dir::Right] {
+            let ngrams = self.ngrams(dir);
+            w.write_all(&(ngrams.len() as u64).to_be_bytes())?;
+            for ngram in ngrams {
+                w.write_all(&ngram.0.to_be_bytes())?;
+            }
+        }
+        w.flush()
+    }
+
+    /**
+     * Reads a structure written by `write_to` for a machine with `state_count` states. Fails with
+     * `InvalidData` on a radius out of range, on a blank symbol other than 0 or 1, on a context
+     * whose state is 0 or above `state_count`, or on a context or ngram with bits set beyond the
+     * radius. The counts of ngrams added by saturation and the phase times are not stored, so
+     * they start again from zero.
+     */
+    pub fn read_from(mut r: impl Read, state_count: u8) -> io::Result<PartialReachable> {
+        fn invalid(message: String) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        }
+        fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+            let mut bytes = [0; 8];
+            r.read_exact(&mut bytes)?;
+            Ok(u64::from_be_bytes(bytes))
+        }
+
+        let mut sides = [0; 2];
+        r.read_exact(&mut sides)?;
+        let radius = Radius::asymmetric(sides[0], sides[1]).ok_or_else(|| {
+            invalid(format!(
+                "radius {}/{} is out of range, each side must lie in [{}, {}]",
+                sides[0],
+                sides[1],
+                Radius::MIN,
+                Radius::MAX
+            ))
+        })?;
+
+        let mut byte = [0; 1];
+        r.read_exact(&mut byte)?;
+        let blank = match byte[0] {
+            0 => Bit(false),
+            1 => Bit(true),
+            other => return Err(invalid(format!("blank symbol {other} is not 0 or 1"))),
+        };
+
+        let mut reachable_local_contexts = ContextSet::new(radius);
+        for _ in 0..read_u64(&mut r)? {
+            r.read_exact(&mut byte)?;
+            let state = State(byte[0]);
+            if !(1..=state_count).contains(&state.0) {
+                return Err(invalid(format!(
+                    "context state {} is not one of the machine's {state_count} states",
+                    state.0
+                )));
+            }
+            let nearby_bits = read_u64(&mut r)?;
+            if nearby_bits >> radius.width() != 0 {
+                return Err(invalid(format!(
+                    "context bits {nearby_bits:#x} do not fit in radius {radius}"
+                )));
+            }
+            reachable_local_contexts.insert(LocalContext::new(state, nearby_bits));
+        }
+
+        let mut reachable_ngrams = DirMap::new(BTreeSet::new());
+        for dir in [Dir::Left, Dir::Right] {
+            for _ in 0..read_u64(&mut r)? {
+                let mut bytes = [0; 4];
+                r.read_exact(&mut bytes)?;
+                let ngram = NGram(NGramBits::from_be_bytes(bytes));
+                if u64::from(ngram.0) & !low_bits(radius.side(dir)) != 0 {
+                    return Err(invalid(format!(
+                        "ngram {:#x} does not fit in radius {radius}",
+                        ngram.0
+                    )));
+                }
+                reachable_ngrams[dir].insert(ngram);
+            }
+        }
+
+        Ok(PartialReachable {
+            radius,
+            blank,
+            reachable_local_contexts,
+            reachable_ngrams,
+            ngram_adds: DirMap::new(0),
+            requeues: 0,
+            revisited_contexts: 0,
+            saturate_time: Duration::ZERO,
+            check_time: Duration::ZERO,
+        })
+    }
+
+    /**
+     * For each reachable local context, the reachable contexts one program step can lead to:
+     * none if it halts, and otherwise one or both of the contexts for the two bits that could be
+     * pulled in. For a closed set, this is the machine's behavior as a finite automaton.
+     */
+    pub fn transition_relation(
+        &self,
+        program: &Program,
+    ) -> BTreeMap<LocalContext, Vec<LocalContext>> {
+        self.reachable_local_contexts
+            .iter()
+            .map(|local_context| {
+                let successors = match step_context(program, local_context, self.radius) {
+                    StepResult::Step { successors, .. } => successors
+                        .into_iter()
+                        .filter(|successor| self.reachable_local_contexts.contains(successor))
+                        .collect(),
+                    StepResult::Halt => Vec::new(),
                 };
+                (local_context, successors)
+            })
+            .collect()
+    }
 
-            // Suppose the action says to move left. This is the naming convention we use:
-            let dir = action.2;
+    /**
+     * A path through `transition_relation` from the blank start context to a context whose
+     * action halts, both included, so that each context steps to the next; or `None` if no
+     * reachable context halts. The search is breadth-first, so the path is a shortest one.
+     */
+    pub fn path_to_halt(&self, program: &Program) -> Option<Vec<LocalContext>> {
+        let relation = self.transition_relation(program);
+        let start = start_context(self.radius, self.blank);
+        let mut parents = BTreeMap::new();
+        let mut seen: BTreeSet<LocalContext> = [start].into_iter().collect();
+        let mut queue: VecDeque<LocalContext> = [start].into_iter().collect();
+        while let Some(local_context) = queue.pop_front() {
+            if let StepResult::Halt = step_context(program, local_context, self.radius) {
+                let mut path = vec![local_context];
+                while let Some(&parent) = parents.get(path.last().expect("path is not empty")) {
+                    path.push(parent);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &successor in relation.get(&local_context).into_iter().flatten() {
+                if seen.insert(successor) {
+                    parents.insert(successor, local_context);
+                    queue.push_back(successor);
+                }
+            }
+        }
+        None
+    }
 
-            // Since we are moving "left", the opposite side (right) must have an ngram "fall off" of the local context.
-            let ngram_falling_off_right = local_context.get(dir.opposite(), self.radius);
-            if !self.reachable_ngrams[dir.opposite()].contains(&ngram_falling_off_right) {
-                // If we don't already have `ngram_falling_off_right` marked as reachable, fix that by marking it reachable.
-                // Since we extended the set of reachable things, we also have to start over and check them all again.
-                return false;
+    /**
+     * How many reachable local contexts there are in each state. States with none are left out.
+     */
+    pub fn contexts_by_state(&self) -> BTreeMap<State, usize> {
+        let mut counts = BTreeMap::new();
+        for local_context in self.reachable_local_contexts.iter() {
+            *counts.entry(local_context.state).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /**
+     * Whether the local context with the head in `state` reading `center`, between `left` and
+     * `right`, is in the reachable set.
+     */
+    pub fn is_reachable(&self, state: State, center: Bit, left: NGram, right: NGram) -> bool {
+        self.reachable_local_contexts
+            .contains(&LocalContext::from_parts(
+                state,
+                left,
+                center,
+                right,
+                self.radius,
+            ))
+    }
+
+    /**
+     * What one program step from `local_context` implies about the reachable set, as it currently
+     * stands. Returns `None` if the context halts.
+     * Both saturation and the closure check are built on this, so that they cannot disagree about
+     * what a closed set is.
+     */
+    fn implications(&self, program: &Program, local_context: LocalContext) -> Option<Implications> {
+        let (dir, falling_off, successors) = match step_context(program, local_context, self.radius)
+        {
+            StepResult::Step {
+                dir,
+                falling_off,
+                successors,
+            } => (dir, falling_off, successors),
+            StepResult::Halt => return None,
+        };
+
+        let successors = successors.map(|discovered_context| {
+            // Check whether the ngram pulled in on the `dir` side is known. If it is not, then we
+            // cannot (yet) reach this context, so we can skip it. But if it is known, then this
+            // new context can be reached in a single step.
+            let discovered_ngram = discovered_context.get(dir, self.radius);
+            if !self.reachable_ngrams[dir].contains(&discovered_ngram) {
+                Successor::Unsupported(discovered_ngram)
+            } else if !self.reachable_local_contexts.contains(&discovered_context) {
+                Successor::New(discovered_context)
+            } else {
+                Successor::Known
             }
+        });
 
-            // A single step causes us to write the center bit, and then push a new bit onto the left.
-            // We don't know what that bit is, just that it's either 0 or 1. Therefore, we separately
-            // check both cases.
-
-            for discovered_bit in [Bit(false), Bit(true)] {
-                // If the pushed bit is 0, then we check whether the new left-half of the context is known.
-                // If it is not known, then we cannot reach this context, so we can skip it.
-                // But if the left half is known, then this new context can be reached in a single step.
-                let discovered_context = local_context
-                    .write_center(action.1, action.0, self.radius)
-                    .push(dir, discovered_bit, self.radius);
-                if self.reachable_ngrams[dir].contains(&discovered_context.get(dir, self.radius))
-                    && !self.reachable_local_contexts.contains(&discovered_context)
-                {
-                    // When the left half is known but the context as a whole is not, mark it as known
-                    // and start over.
-                    return false;
+        Some(Implications {
+            dir,
+            falling_off,
+            falling_off_known: self.reachable_ngrams[dir.opposite()].contains(&falling_off),
+            successors,
+        })
+    }
+
+    /**
+     * Checks whether the reachable set is closed under a single program step.
+     * A reachable context which halts is reported as `HaltingTransition` in preference to any
+     * other way of not being closed, since it means that no budget would make this radius work.
+     */
+    fn check_if_closed_under_program_step(&self, program: &Program) -> Result<(), MayHalt> {
+        let mut closed = true;
+        for local_context in self.reachable_local_contexts.iter() {
+            if !closed {
+                // Only keep looking for halting contexts, which needs nothing but the step itself.
+                if let StepResult::Halt = step_context(program, local_context, self.radius) {
+                    return Err(MayHalt::HaltingTransition);
                 }
+                continue;
+            }
+            let implications = match self.implications(program, local_context) {
+                Some(implications) => implications,
+                None => return Err(MayHalt::HaltingTransition),
+            };
+
+            // If we don't already have `falling_off` marked as reachable, or a successor that can
+            // be reached is not known, we are not closed.
+            if !implications.falling_off_known
+                || implications
+                    .successors
+                    .iter()
+                    .any(|successor| matches!(successor, Successor::New(_)))
+            {
+                closed = false;
             }
         }
 
-        true
+        if closed {
+            Ok(())
+        } else {
+            Err(MayHalt::NotClosed)
+        }
     }
 
     /**
      * Adds more, to quickly saturate, does not check for saturation.
+     * Returns why it stopped early, if it did: on reaching a halting context or running out of budget.
+     * `progress` is called every `PROGRESS_INTERVAL` work items with the reachable context count.
      */
-    fn add_to_saturate_quick(&mut self, program: &Program, max_context_count: usize) {
-        let mut work_queue_local: Vec<LocalContext> =
-            self.reachable_local_contexts.iter().cloned().collect();
+    fn add_to_saturate_quick(
+        &mut self,
+        program: &Program,
+        budget: &Budget,
+        mut progress: impl FnMut(usize),
+    ) -> Option<MayHalt> {
+        // Always take the smallest pending context, so that the order contexts are found in (and
+        // so the partial set left when a budget runs out) depends only on the program.
+        let mut work_queue_local: BinaryHeap<Reverse<LocalContext>> =
+            self.reachable_local_contexts.iter().map(Reverse).collect();
 
         let mut work_queue_grams: DirMap<BTreeMap<NGram, Vec<LocalContext>>> =
             DirMap::new(BTreeMap::new());
+        // The contexts queued again so far, only to count them.
+        let mut requeued = ContextSet::new(self.radius);
 
-        while let Some(local_context) = work_queue_local.pop() {
-            if self.reachable_local_contexts.len() > max_context_count {
+        let mut iterations: usize = 0;
+        while let Some(Reverse(local_context)) = work_queue_local.pop() {
+            iterations += 1;
+            if iterations.is_multiple_of(PROGRESS_INTERVAL) {
+                progress(self.reachable_local_contexts.len());
+            }
+            if let Some(exhausted) = budget.exhausted(self, iterations) {
                 // Give up, it has taken too long.
-                return;
+                return Some(exhausted);
             }
 
-            let action =
-                match program.action(local_context.get_center(self.radius), local_context.state) {
-                    Ok(action) => action,
-                    _ => {
-                        // Stop, since we hit a halting state.
-                        return;
-                    }
-                };
+            let implications = match self.implications(program, local_context) {
+                Some(implications) => implications,
+                None => {
+                    // Stop, since we hit a halting state.
+                    return Some(MayHalt::HaltingTransition);
+                }
+            };
+            let dir = implications.dir;
 
-            // Suppose the action says to move left. This is the naming convention we use:
-            let dir = action.2;
-
-            // Since we are moving "left", the opposite side (right) must have an ngram "fall off" of the local context.
-            let ngram_falling_off_right = local_context.get(dir.opposite(), self.radius);
-            if !self.reachable_ngrams[dir.opposite()].contains(&ngram_falling_off_right) {
-                // If we don't already have `ngram_falling_off_right` marked as reachable, fix that by marking it reachable.
-                // Since we extended the set of reachable things, we also have to start over and check them all again.
-                self.reachable_ngrams[dir.opposite()].insert(ngram_falling_off_right);
-
-                if work_queue_grams[dir.opposite()].contains_key(&ngram_falling_off_right) {
-                    // Move all of these items into the main queue.
-                    for revisit_local in work_queue_grams[dir.opposite()]
-                        .remove(&ngram_falling_off_right)
-                        .unwrap()
-                    {
-                        // Revisit this one, since it was waiting on this ngram being available.
-                        work_queue_local.push(revisit_local);
+            if !implications.falling_off_known {
+                // We didn't already have `falling_off` marked as reachable, so now that it is,
+                // revisit everything that was waiting on it.
+                self.reachable_ngrams[dir.opposite()].insert(implications.falling_off);
+                self.ngram_adds[dir.opposite()] += 1;
+                if let Some(waiting) =
+                    work_queue_grams[dir.opposite()].remove(&implications.falling_off)
+                {
+                    self.requeues += waiting.len();
+                    for context in waiting {
+                        if requeued.insert(context) {
+                            self.revisited_contexts += 1;
+                        }
+                        work_queue_local.push(Reverse(context));
                     }
                 }
             }
 
-            // A single step causes us to write the center bit, and then push a new bit onto the left.
-            // We don't know what that bit is, just that it's either 0 or 1. Therefore, we separately
-            // check both cases.
-
-            for discovered_bit in [Bit(false), Bit(true)] {
-                // If the pushed bit is 0, then we check whether the new left-half of the context is known.
-                // If it is not known, then we cannot reach this context, so we can skip it.
-                // But if the left half is known, then this new context can be reached in a single step.
-                let discovered_context = local_context
-                    .write_center(action.1, action.0, self.radius)
-                    .push(dir, discovered_bit, self.radius);
+            for successor in implications.successors {
+                match successor {
+                    Successor::New(discovered_context) => {
+                        // Mark it as known and start over from it.
+                        self.reachable_local_contexts.insert(discovered_context);
+                        if budget.fast_reject
+                            && step_context(program, discovered_context, self.radius)
+                                == StepResult::Halt
+                        {
+                            // It would stop saturation once popped anyway, so stop right away.
+                            return Some(MayHalt::HaltingTransition);
+                        }
+                        work_queue_local.push(Reverse(discovered_context));
+                    }
+                    Successor::Unsupported(discovered_ngram) => {
+                        // Remember that we are waiting on this gram, so that if it appears,
+                        // we can revisit things.
+                        work_queue_grams[dir]
+                            .entry(discovered_ngram)
+                            .or_default()
+                            .push(local_context);
+                    }
+                    Successor::Known => {}
+                }
+            }
+        }
+        None
+    }
 
-                let discovered_ngram = discovered_context.get(dir, self.radius);
+    /**
+     * Panics if any reachable local context has a halting action. A set that was confirmed closed
+     * can never contain one, so this only fires on a bug in saturation or the closure check.
+     */
+    pub fn assert_sound(&self, program: &Program) {
+        for local_context in self.reachable_local_contexts.iter() {
+            if let StepResult::Halt = step_context(program, local_context, self.radius) {
+                panic!(
+                    "unsound decision: machine declared looping, but reachable context {} halts",
+                    local_context.display(self.radius)
+                );
+            }
+        }
+    }
 
-                if self.reachable_ngrams[dir].contains(&discovered_ngram)
-                    && !self.reachable_local_contexts.contains(&discovered_context)
-                {
-                    // When the left half is known but the context as a whole is not, mark it as known
-                    // and start over.
-                    self.reachable_local_contexts.insert(discovered_context);
-                    work_queue_local.push(discovered_context);
-                } else {
-                    // Otherwise, remember that we are waiting on this gram, so that if it appears,
-                    // we can revisit things.
-                    work_queue_grams[dir]
-                        .entry(discovered_ngram)
-                        .or_default()
-                        .push(local_context);
-                }
+    /**
+     * Panics if a reachable local context has neither its left ngram among the left reachable
+     * ngrams nor its right ngram among the right ones.
+     * A context is only ever added with the ngram on the side it was pulled in from already
+     * reachable (and the start context has both), so one side always matches; the other side is
+     * bits the head has just written, which need not have fallen off the window yet.
+     */
+    pub fn assert_consistent(&self) {
+        for local_context in self.reachable_local_contexts.iter() {
+            if !self.reachable_ngrams[Dir::Left].contains(&local_context.get_left(self.radius))
+                && !self.reachable_ngrams[Dir::Right]
+                    .contains(&local_context.get_right(self.radius))
+            {
+                panic!(
+                    "inconsistent reachable set: neither ngram of reachable context {} is reachable on its side",
+                    local_context.display(self.radius)
+                );
             }
         }
     }
@@ -257,55 +859,898 @@ impl PartialReachable {
     fn confirm_closed_under_program(
         &mut self,
         program: &Program,
-        max_context_count: usize,
+        budget: &Budget,
+        progress: impl FnMut(usize),
     ) -> Result<LoopsForever, MayHalt> {
-        self.add_to_saturate_quick(program, max_context_count);
+        let saturate_start = Instant::now();
+        let stopped_early = self.add_to_saturate_quick(program, budget, progress);
+        let check_start = Instant::now();
+        let closed = self.check_if_closed_under_program_step(program);
+        self.saturate_time += check_start - saturate_start;
+        self.check_time += check_start.elapsed();
 
-        if self.check_if_closed_under_program_step(program) {
-            Ok(LoopsForever)
-        } else {
-            Err(MayHalt)
+        match closed {
+            Ok(()) => Ok(LoopsForever),
+            Err(MayHalt::HaltingTransition) => Err(MayHalt::HaltingTransition),
+            // Not being closed is expected when saturation gave up, so report why it did.
+            Err(not_closed) => Err(stopped_early.unwrap_or(not_closed)),
+        }
+    }
+}
+
+/**
+ * Why a reachable set is not a proof that its machine loops forever.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum CertificateError {
+    /**
+     * The start context, with the head in state A on blank tape, or the blank ngram on one side,
+     * is not in the set.
+     */
+    MissingStart,
+    /**
+     * The context `context` in the set halts.
+     */
+    Halts { context: LocalContext },
+    /**
+     * Stepping `context` drops the ngram `falling_off` on the `side` side, where it is not in the set.
+     */
+    MissingNGram {
+        context: LocalContext,
+        side: Dir,
+        falling_off: NGram,
+    },
+    /**
+     * Stepping `context` pulls in a reachable ngram, leading to `successor`, which is not in the set.
+     */
+    MissingSuccessor {
+        context: LocalContext,
+        successor: LocalContext,
+    },
+}
+
+impl CertificateError {
+    /**
+     * Describes the error, showing its contexts at `radius`, the radius of the rejected set.
+     */
+    pub fn describe(self, radius: Radius) -> String {
+        match self {
+            CertificateError::MissingStart => {
+                "the blank start context or a blank ngram is missing".to_string()
+            }
+            CertificateError::Halts { context } => {
+                format!("context {} halts", context.display(radius))
+            }
+            CertificateError::MissingNGram {
+                context,
+                side,
+                falling_off,
+            } => format!(
+                "context {} drops the ngram {} on the {side} side, which is missing",
+                context.display(radius),
+                falling_off.to_bits_string(radius.side(side))
+            ),
+            CertificateError::MissingSuccessor { context, successor } => format!(
+                "context {} leads to {}, which is missing",
+                context.display(radius),
+                successor.display(radius)
+            ),
+        }
+    }
+}
+
+/**
+ * Checks that `certificate` proves that `program` loops forever from a tape filled with the
+ * certificate's blank symbol: that it holds the blank start,
+ * has no halting context, and is closed under a program step.
+ * This deliberately works from `step_context` alone, rather than sharing the code saturation
+ * and its own closure check use, so that it can catch a bug in either.
+ */
+pub fn verify_certificate(
+    program: &Program,
+    certificate: &PartialReachable,
+) -> Result<(), CertificateError> {
+    let radius = certificate.radius;
+    let contexts = &certificate.reachable_local_contexts;
+    let ngrams = &certificate.reachable_ngrams;
+    let blank = certificate.blank;
+    if !contexts.contains(&start_context(radius, blank))
+        || !ngrams[Dir::Left].contains(&blank_ngram(radius.left, blank))
+        || !ngrams[Dir::Right].contains(&blank_ngram(radius.right, blank))
+    {
+        return Err(CertificateError::MissingStart);
+    }
+    for context in contexts.iter() {
+        let (dir, falling_off, successors) = match step_context(program, context, radius) {
+            StepResult::Step {
+                dir,
+                falling_off,
+                successors,
+            } => (dir, falling_off, successors),
+            StepResult::Halt => return Err(CertificateError::Halts { context }),
+        };
+        if !ngrams[dir.opposite()].contains(&falling_off) {
+            return Err(CertificateError::MissingNGram {
+                context,
+                side: dir.opposite(),
+                falling_off,
+            });
+        }
+        for successor in successors {
+            if ngrams[dir].contains(&successor.get(dir, radius)) && !contexts.contains(&successor) {
+                return Err(CertificateError::MissingSuccessor { context, successor });
+            }
         }
     }
+    Ok(())
 }
 
 impl NGram {
-    pub fn print(self, r: Radius) {
-        for i in 0..r.0 {
-            if (self.0 & (1 << i)) != 0 {
-                print!("1");
-            } else {
-                print!("0");
+    /**
+     * The `len` bits of this ngram, from left to right, e.g. `0110`. An ngram on the `dir` side
+     * of a context at radius `r` has `r.side(dir)` bits.
+     */
+    pub fn to_bits_string(self, len: u8) -> String {
+        (0..len)
+            // Past the bits of an `NGramBits`, the ngram is all 0s.
+            .map(|i| match self.0.checked_shr(u32::from(i)) {
+                Some(shifted) if shifted & 1 != 0 => '1',
+                _ => '0',
+            })
+            .collect()
+    }
+    pub fn print(self, len: u8) {
+        print!("{}", self.to_bits_string(len));
+    }
+}
+
+/**
+ * Formats a `LocalContext` at a given radius, as the bits of the window from left to right with
+ * the state and the center bit in brackets, e.g. `00[A1]01`.
+ */
+pub struct DisplayLocalContext {
+    context: LocalContext,
+    radius: Radius,
+}
+
+impl std::fmt::Display for DisplayLocalContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let center = self.radius.left;
+        for i in 0..self.radius.width() {
+            if i == center {
+                write!(f, "[{}", self.context.state)?;
+            }
+            write!(
+                f,
+                "{}",
+                Bit((self.context.nearby_bits & window_bit(i)) != 0)
+            )?;
+            if i == center {
+                write!(f, "]")?;
             }
         }
+        Ok(())
     }
 }
+
 impl LocalContext {
+    pub fn display(self, r: Radius) -> DisplayLocalContext {
+        DisplayLocalContext {
+            context: self,
+            radius: r,
+        }
+    }
+    pub fn to_bits_string(self, r: Radius) -> String {
+        self.display(r).to_string()
+    }
     pub fn print(self, r: Radius) {
-        for i in 0..2 * r.0 + 1 {
-            if i == r.0 {
-                print!("[");
-                print!("{}", (b'A' - 1 + self.state.0) as char);
+        print!("{}", self.display(r));
+    }
+}
+
+pub type ClassifyOutcome = Result<LoopsForever, MayHalt>;
+
+/**
+ * The size of the reachable structure that was built while classifying a machine.
+ * For an undecided machine, this is whatever had been found when saturation stopped.
+ */
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct ClassifyStats {
+    pub reachable_contexts: usize,
+    pub reachable_left_ngrams: usize,
+    pub reachable_right_ngrams: usize,
+    /**
+     * The reachable ngrams themselves, on the left and right of the head respectively.
+     */
+    pub left_ngrams: BTreeSet<NGram>,
+    pub right_ngrams: BTreeSet<NGram>,
+    /**
+     * The states which appear in at least one reachable local context.
+     */
+    pub reachable_states: BTreeSet<State>,
+    /**
+     * How many reachable local contexts there are in each reachable state.
+     */
+    pub contexts_by_state: BTreeMap<State, usize>,
+    /**
+     * The transitions read by at least one reachable local context, as the state and the bit
+     * under the head. A machine never needs the others, whatever they are.
+     */
+    pub transitions_used: BTreeSet<(State, Bit)>,
+    /**
+     * How many reachable local contexts move the head left and right, respectively.
+     */
+    pub left_moves: usize,
+    pub right_moves: usize,
+    /**
+     * The radius the structure was built at, and how many local contexts exist at all at that
+     * radius: `state_count * 2^(left + right + 1)`.
+     */
+    pub radius: Radius,
+    pub possible_contexts: u128,
+    /**
+     * How many ngrams saturation added on the left and right, respectively. Ngrams are added on
+     * the side the head moves away from, so a machine drifting right mostly adds left ngrams.
+     */
+    pub left_ngram_adds: usize,
+    pub right_ngram_adds: usize,
+    /**
+     * How many times saturation queued an already explored context again, because an ngram it
+     * was waiting on had just become reachable, and how many distinct contexts were queued
+     * again. Many of these for few contexts means time spent going over the same ground.
+     */
+    pub requeues: usize,
+    pub revisited_contexts: usize,
+    /**
+     * How long saturation and the closure check took, in total over every radius tried with
+     * `auto_cap_radius`. These vary from run to run, unlike everything else here.
+     */
+    pub saturate_time: Duration,
+    pub check_time: Duration,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Drift {
+    Left,
+    Right,
+    Balanced,
+}
+
+impl ClassifyStats {
+    /**
+     * The fraction of all possible local contexts which are reachable. A tiny fraction for a
+     * machine decided at this radius suggests a much smaller radius would have done.
+     */
+    pub fn context_ratio(&self) -> f64 {
+        if self.possible_contexts == 0 {
+            0.0
+        } else {
+            self.reachable_contexts as f64 / self.possible_contexts as f64
+        }
+    }
+
+    /**
+     * A rough guess at which way the head travels, from the directions taken by the reachable
+     * contexts: `Balanced` unless one direction outnumbers the other by more than 10% of all moves.
+     */
+    pub fn drift(&self) -> Drift {
+        let total = self.left_moves + self.right_moves;
+        if self.left_moves.abs_diff(self.right_moves) * 10 <= total {
+            Drift::Balanced
+        } else if self.left_moves > self.right_moves {
+            Drift::Left
+        } else {
+            Drift::Right
+        }
+    }
+}
+
+impl PartialReachable {
+    pub fn stats(&self, program: &Program) -> ClassifyStats {
+        let mut stats = ClassifyStats {
+            reachable_contexts: self.reachable_local_contexts.len(),
+            reachable_left_ngrams: self.reachable_ngrams[Dir::Left].len(),
+            reachable_right_ngrams: self.reachable_ngrams[Dir::Right].len(),
+            left_ngrams: self.ngrams(Dir::Left),
+            right_ngrams: self.ngrams(Dir::Right),
+            contexts_by_state: self.contexts_by_state(),
+            radius: self.radius,
+            possible_contexts: u128::from(program.state_count) << self.radius.width(),
+            left_ngram_adds: self.ngram_adds[Dir::Left],
+            right_ngram_adds: self.ngram_adds[Dir::Right],
+            requeues: self.requeues,
+            revisited_contexts: self.revisited_contexts,
+            saturate_time: self.saturate_time,
+            check_time: self.check_time,
+            ..ClassifyStats::default()
+        };
+        for local_context in self.reachable_local_contexts.iter() {
+            stats.reachable_states.insert(local_context.state);
+            stats
+                .transitions_used
+                .insert((local_context.state, local_context.get_center(self.radius)));
+            match step_context(program, local_context, self.radius) {
+                StepResult::Step { dir: Dir::Left, .. } => stats.left_moves += 1,
+                StepResult::Step {
+                    dir: Dir::Right, ..
+                } => stats.right_moves += 1,
+                StepResult::Halt => {}
             }
-            if (self.nearby_bits & (1 << i)) != 0 {
-                print!("1");
-            } else {
-                print!("0");
+        }
+        stats
+    }
+}
+
+/**
+ * Everything that controls a classification besides the program itself.
+ * `Default` gives the same settings as the command line defaults:
+ * radius 4 and at most 1,000,000 reachable local contexts, with no other limits.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassifyConfig {
+    /**
+     * The radius on both sides of the head, unless overridden by `radius_left` or
+     * `radius_right`. Each side must lie in [`Radius::MIN`, `Radius::MAX`]: classifying with
+     * any other radius panics.
+     */
+    pub radius: u8,
+    pub radius_left: Option<u8>,
+    pub radius_right: Option<u8>,
+    /**
+     * Give up (reporting `MayHalt`) once more local contexts than this are reachable.
+     * Contexts are explored in sorted order, so the partial set reached by then is always the same
+     * for a given program and configuration.
+     */
+    pub max_context_count: usize,
+    /**
+     * Give up once the reachable structure is estimated to use more than this many bytes.
+     */
+    pub max_memory_bytes: Option<usize>,
+    /**
+     * Give up once saturation has been running for longer than this.
+     */
+    pub timeout: Option<Duration>,
+    /**
+     * Before building the reachable structure, simulate the machine from the blank tape for this
+     * many steps; a machine seen to halt is reported as `MayHalt` immediately.
+     */
+    pub presim_steps: Option<u64>,
+    /**
+     * Whenever a machine is declared `LoopsForever`, re-check that no reachable local context
+     * halts, panicking if one does.
+     */
+    pub check_soundness: bool,
+    /**
+     * After saturation, check the structure's internal invariants with
+     * `PartialReachable::assert_consistent`, panicking if they do not hold.
+     */
+    pub self_check: bool,
+    /**
+     * Also classify the machine's mirror image (see `Program::mirror`), panicking if only one
+     * of the two is declared `LoopsForever`.
+     */
+    pub check_mirror: bool,
+    /**
+     * Whenever a machine is declared `LoopsForever`, write its reachable set out as a
+     * certificate, read it back and check it with `verify_certificate`, panicking if that fails.
+     */
+    pub verify_each: bool,
+    /**
+     * Classify as if these states halted (see `Program::with_halting_states`), e.g. to see
+     * whether the machine's behavior depends on them, without changing the program itself.
+     */
+    pub treat_as_halt: Vec<State>,
+    /**
+     * The symbol every cell of the tape starts out as: 0 in the usual convention, but 1 in some
+     * machine databases.
+     */
+    pub blank: Bit,
+    /**
+     * Give up (reporting `MayHalt::HaltingTransition`) the moment saturation finds a reachable
+     * context that halts, rather than when it gets round to exploring it. Since contexts are
+     * only ever added, such a machine can never be decided at this radius, so this only ever
+     * makes rejecting it quicker, and turns some budget-limited rejections into halting ones.
+     * It makes no difference to which machines are declared `LoopsForever`.
+     */
+    pub fast_reject: bool,
+    /**
+     * Treat `radius` as a cap: try radius 1, 2, 4, ... up to it, stopping at the first that
+     * decides the machine, so machines which are easy to decide never pay for a large radius.
+     */
+    pub auto_cap_radius: bool,
+}
+
+impl Default for ClassifyConfig {
+    fn default() -> Self {
+        ClassifyConfig {
+            radius: 4,
+            radius_left: None,
+            radius_right: None,
+            max_context_count: 1_000_000,
+            max_memory_bytes: None,
+            timeout: None,
+            presim_steps: None,
+            check_soundness: false,
+            self_check: false,
+            check_mirror: false,
+            verify_each: false,
+            treat_as_halt: Vec::new(),
+            blank: Bit(false),
+            fast_reject: false,
+            auto_cap_radius: false,
+        }
+    }
+}
+
+impl ClassifyConfig {
+    pub fn new(radius: impl Into<Radius>) -> Self {
+        ClassifyConfig::default().with_radius(radius)
+    }
+    /**
+     * Sets the radius, replacing any per-side override: an asymmetric `Radius` sets both.
+     */
+    pub fn with_radius(self, radius: impl Into<Radius>) -> Self {
+        let radius = radius.into();
+        let side = |side| (!radius.is_symmetric()).then_some(side);
+        ClassifyConfig {
+            radius: radius.get(),
+            radius_left: side(radius.left),
+            radius_right: side(radius.right),
+            ..self
+        }
+    }
+    pub fn with_radius_left(self, radius_left: u8) -> Self {
+        ClassifyConfig {
+            radius_left: Some(radius_left),
+            ..self
+        }
+    }
+    pub fn with_radius_right(self, radius_right: u8) -> Self {
+        ClassifyConfig {
+            radius_right: Some(radius_right),
+            ..self
+        }
+    }
+
+    /**
+     * The radius classification runs at (or, with `auto_cap_radius`, is capped at), after
+     * applying the per-side overrides.
+     */
+    pub fn effective_radius(&self) -> Result<Radius, InvalidRadius> {
+        let left = self.radius_left.unwrap_or(self.radius);
+        let right = self.radius_right.unwrap_or(self.radius);
+        let valid = |side| Radius::new(side).ok_or(InvalidRadius(side));
+        Ok(Radius {
+            left: valid(left)?.left,
+            right: valid(right)?.right,
+        })
+    }
+    pub fn with_max_context_count(self, max_context_count: usize) -> Self {
+        ClassifyConfig {
+            max_context_count,
+            ..self
+        }
+    }
+    pub fn with_max_memory_bytes(self, max_memory_bytes: usize) -> Self {
+        ClassifyConfig {
+            max_memory_bytes: Some(max_memory_bytes),
+            ..self
+        }
+    }
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        ClassifyConfig {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+    pub fn with_presim_steps(self, presim_steps: u64) -> Self {
+        ClassifyConfig {
+            presim_steps: Some(presim_steps),
+            ..self
+        }
+    }
+    pub fn with_check_soundness(self, check_soundness: bool) -> Self {
+        ClassifyConfig {
+            check_soundness,
+            ..self
+        }
+    }
+    pub fn with_self_check(self, self_check: bool) -> Self {
+        ClassifyConfig { self_check, ..self }
+    }
+    pub fn with_check_mirror(self, check_mirror: bool) -> Self {
+        ClassifyConfig {
+            check_mirror,
+            ..self
+        }
+    }
+    pub fn with_verify_each(self, verify_each: bool) -> Self {
+        ClassifyConfig {
+            verify_each,
+            ..self
+        }
+    }
+    pub fn with_treat_as_halt(self, treat_as_halt: &[State]) -> Self {
+        ClassifyConfig {
+            treat_as_halt: treat_as_halt.to_vec(),
+            ..self
+        }
+    }
+    pub fn with_blank(self, blank: Bit) -> Self {
+        ClassifyConfig { blank, ..self }
+    }
+    pub fn with_fast_reject(self, fast_reject: bool) -> Self {
+        ClassifyConfig {
+            fast_reject,
+            ..self
+        }
+    }
+    pub fn with_auto_cap_radius(self, auto_cap_radius: bool) -> Self {
+        ClassifyConfig {
+            auto_cap_radius,
+            ..self
+        }
+    }
+}
+
+/**
+ * The limits from a `ClassifyConfig`, as checked while saturating, and whether to stop as soon
+ * as a halting context is found.
+ */
+struct Budget {
+    max_context_count: usize,
+    max_memory_bytes: Option<usize>,
+    deadline: Option<Instant>,
+    fast_reject: bool,
+}
+
+/**
+ * A rough per-context cost of the reachable structure, including the set node overhead and the
+ * work queues, used to estimate memory use against `max_memory_bytes`.
+ */
+const APPROXIMATE_BYTES_PER_CONTEXT: usize = 48;
+
+impl Budget {
+    fn new(config: &ClassifyConfig) -> Self {
+        Budget {
+            max_context_count: config.max_context_count,
+            max_memory_bytes: config.max_memory_bytes,
+            deadline: config.timeout.map(|timeout| Instant::now() + timeout),
+            fast_reject: config.fast_reject,
+        }
+    }
+
+    /**
+     * Whether saturation should give up, after it has processed `iterations` work items,
+     * and if so which limit it ran into.
+     */
+    fn exhausted(&self, reachable: &PartialReachable, iterations: usize) -> Option<MayHalt> {
+        let context_count = reachable.reachable_local_contexts.len();
+        if context_count > self.max_context_count {
+            return Some(MayHalt::ContextBudget);
+        }
+        if let Some(max_memory_bytes) = self.max_memory_bytes {
+            if context_count.saturating_mul(APPROXIMATE_BYTES_PER_CONTEXT) > max_memory_bytes {
+                return Some(MayHalt::MemoryBudget);
             }
-            if i == r.0 {
-                print!("]");
+        }
+        if let Some(deadline) = self.deadline {
+            // Reading the clock is comparatively slow, so only do it every so often.
+            if iterations.is_multiple_of(1024) && Instant::now() > deadline {
+                return Some(MayHalt::Timeout);
             }
         }
+        None
     }
 }
 
+/**
+ * Classifies `program` at `radius`, either a `Radius` or a `u8` for the same radius on both sides,
+ * which panics if it is out of range.
+ */
 pub fn classify(
     program: &Program,
-    radius: u8,
+    radius: impl Into<Radius>,
     max_context_count: usize,
-) -> Result<LoopsForever, MayHalt> {
-    let mut reachable = PartialReachable::new(radius);
-    assert!(radius >= 1);
-    assert!(radius <= 31);
-    reachable.confirm_closed_under_program(program, max_context_count)
+) -> ClassifyOutcome {
+    classify_with_config(
+        program,
+        &ClassifyConfig::new(radius).with_max_context_count(max_context_count),
+    )
+}
+
+/**
+ * How hard `program` is for this decider: the number of reachable local contexts in the closed
+ * set proving that it loops forever, or `None` if it is undecided within the budget.
+ */
+pub fn cps_complexity(program: &Program, radius: u8, max_context_count: usize) -> Option<usize> {
+    let config = ClassifyConfig::new(radius).with_max_context_count(max_context_count);
+    match classify_with_stats(program, &config) {
+        (Ok(LoopsForever), stats) => Some(stats.reachable_contexts),
+        (Err(_), _) => None,
+    }
+}
+
+pub fn classify_with_config(program: &Program, config: &ClassifyConfig) -> ClassifyOutcome {
+    classify_retaining(program, config).0
+}
+
+pub fn classify_with_stats(
+    program: &Program,
+    config: &ClassifyConfig,
+) -> (ClassifyOutcome, ClassifyStats) {
+    let (outcome, reachable) = classify_retaining(program, config);
+    // The structure was built for the machine as classified, with `treat_as_halt` applied.
+    let program = program.with_halting_states(&config.treat_as_halt);
+    (outcome, reachable.stats(&program))
+}
+
+/**
+ * Whether the blank start context alone is closed under a program step at radius 1: that is,
+ * A0 writes 0 and stays in A, so the machine walks forever over blank tape. This is much cheaper
+ * than a full classification, and conservative: it is false for anything it cannot prove.
+ */
+pub fn is_trivial_loop(program: &Program) -> bool {
+    is_trivial_loop_over(program, Bit(false))
+}
+
+/**
+ * Like `is_trivial_loop`, on a tape filled with `blank`.
+ */
+fn is_trivial_loop_over(program: &Program, blank: Bit) -> bool {
+    PartialReachable::new(Radius::default(), blank)
+        .check_if_closed_under_program_step(program)
+        .is_ok()
+}
+
+/**
+ * Classifies `program`, also returning the reachable structure that was built, which can be
+ * queried afterwards. For a looping machine, it is a closed set covering every configuration.
+ */
+pub fn classify_retaining(
+    program: &Program,
+    config: &ClassifyConfig,
+) -> (ClassifyOutcome, PartialReachable) {
+    classify_observing(program, config, |_| {})
+}
+
+/**
+ * Classifies `program`, calling `progress` with the reachable context count every so often
+ * while saturating, e.g. to show a long classification's growth towards the context budget.
+ */
+pub fn classify_with_callback(
+    program: &Program,
+    config: &ClassifyConfig,
+    progress: impl FnMut(usize),
+) -> ClassifyOutcome {
+    classify_observing(program, config, progress).0
+}
+
+fn classify_observing(
+    program: &Program,
+    config: &ClassifyConfig,
+    progress: impl FnMut(usize),
+) -> (ClassifyOutcome, PartialReachable) {
+    let (outcome, reachable) = classify_capped(program, config, progress);
+    if config.check_mirror {
+        // The mirror image's left is the machine's right.
+        let mirror_config = ClassifyConfig {
+            radius_left: Some(config.radius_right.unwrap_or(config.radius)),
+            radius_right: Some(config.radius_left.unwrap_or(config.radius)),
+            check_mirror: false,
+            ..config.clone()
+        };
+        let mirror_outcome = classify_capped(&program.mirror(), &mirror_config, |_| {}).0;
+        // A timeout depends on the machine's load as much as on the machine.
+        let timed_out = [outcome, mirror_outcome].contains(&Err(MayHalt::Timeout));
+        if !timed_out && outcome.is_ok() != mirror_outcome.is_ok() {
+            panic!(
+                "directional bug: {program} was classified as {outcome:?}, but its mirror image {} as {mirror_outcome:?}",
+                program.mirror()
+            );
+        }
+    }
+    (outcome, reachable)
+}
+
+/**
+ * Classifies `program` at `config.radius`, or at every radius up to it with `auto_cap_radius`.
+ */
+fn classify_capped(
+    program: &Program,
+    config: &ClassifyConfig,
+    mut progress: impl FnMut(usize),
+) -> (ClassifyOutcome, PartialReachable) {
+    let max_radius = config
+        .effective_radius()
+        .unwrap_or_else(|err| panic!("{err}"));
+    if !config.auto_cap_radius {
+        return classify_at(program, config, max_radius, progress);
+    }
+    let mut radius = 1;
+    let (mut saturate_time, mut check_time) = (Duration::ZERO, Duration::ZERO);
+    loop {
+        let capped = Radius {
+            left: radius.min(max_radius.left),
+            right: radius.min(max_radius.right),
+        };
+        let (outcome, mut reachable) = classify_at(program, config, capped, &mut progress);
+        if outcome.is_ok() || capped == max_radius {
+            reachable.saturate_time += saturate_time;
+            reachable.check_time += check_time;
+            return (outcome, reachable);
+        }
+        saturate_time += reachable.saturate_time;
+        check_time += reachable.check_time;
+        radius = radius.saturating_mul(2);
+    }
+}
+
+/**
+ * Classifies `program` at `radius`, with all other settings taken from `config`.
+ */
+fn classify_at(
+    program: &Program,
+    config: &ClassifyConfig,
+    radius: Radius,
+    progress: impl FnMut(usize),
+) -> (ClassifyOutcome, PartialReachable) {
+    let restricted;
+    let program = if config.treat_as_halt.is_empty() {
+        program
+    } else {
+        restricted = program.with_halting_states(&config.treat_as_halt);
+        &restricted
+    };
+    let mut reachable = PartialReachable::new(radius, config.blank);
+    if step_context(program, start_context(radius, config.blank), radius) == StepResult::Halt {
+        // There is nothing to saturate, or to simulate, before the very first step halts.
+        return (Err(MayHalt::StuckAtStart), reachable);
+    }
+    if is_trivial_loop_over(program, config.blank) {
+        // The start context steps back to itself, which is just as closed at any other radius.
+        debug_assert!(reachable
+            .check_if_closed_under_program_step(program)
+            .is_ok());
+        if config.verify_each {
+            verify_round_trip(program, &reachable);
+        }
+        return (Ok(LoopsForever), reachable);
+    }
+    if let Some(presim_steps) = config.presim_steps {
+        // Over blank 1s, the machine runs like its inverse does over blank 0s.
+        let presim_halts = if config.blank.0 {
+            steps_to_halt(&program.invert_bits(), presim_steps)
+        } else {
+            steps_to_halt(program, presim_steps)
+        };
+        if let Some(steps) = presim_halts {
+            return (Err(MayHalt::HaltedInPresim { steps }), reachable);
+        }
+    }
+    let outcome = reachable.confirm_closed_under_program(program, &Budget::new(config), progress);
+    if config.check_soundness && outcome.is_ok() {
+        reachable.assert_sound(program);
+    }
+    if config.self_check {
+        reachable.assert_consistent();
+    }
+    if config.verify_each && outcome.is_ok() {
+        verify_round_trip(program, &reachable);
+    }
+    (outcome, reachable)
+}
+
+/**
+ * Writes `reachable` out as a certificate and checks what is read back, panicking if it does not
+ * prove that `program` loops forever.
+ */
+fn verify_round_trip(program: &Program, reachable: &PartialReachable) {
+    let mut bytes = Vec::new();
+    reachable
+        .write_to(&mut bytes)
+        .expect("writing to a Vec cannot fail");
+    let certificate = PartialReachable::read_from(bytes.as_slice(), program.state_count)
+        .unwrap_or_else(|err| panic!("unreadable certificate for {program}: {err}"));
+    if let Err(err) = verify_certificate(program, &certificate) {
+        panic!(
+            "certificate check failed: machine {program} declared looping, but {}",
+            err.describe(certificate.radius)
+        );
+    }
+}
+
+/**
+ * Classifies `program` once per entry of `radii`, returning the outcomes in the same order.
+ * Apart from the radius, every classification uses `config`.
+ * Each radius is decided independently: a closed set at one radius does not project onto a
+ * closed set at another, so there is nothing sound to warm-start from.
+ */
+pub fn classify_multi(
+    program: &Program,
+    radii: &[u8],
+    config: &ClassifyConfig,
+) -> Vec<ClassifyOutcome> {
+    radii
+        .iter()
+        .map(|&radius| classify_with_config(program, &config.clone().with_radius(radius)))
+        .collect()
+}
+
+/**
+ * Classifies `program` at every radius in `1..=max_radius` in parallel, returning the smallest
+ * radius at which it loops forever, if any. Apart from the radius, every classification uses
+ * `config`. Once a radius succeeds, larger radii that have not started yet are skipped, though
+ * the ones already running are left to finish.
+ */
+pub fn classify_min_radius(
+    program: &Program,
+    max_radius: u8,
+    config: &ClassifyConfig,
+) -> Option<u8> {
+    (1..=max_radius).into_par_iter().find_first(|&radius| {
+        classify_with_config(program, &config.clone().with_radius(radius)).is_ok()
+    })
+}
+
+/**
+ * Whether `program` loops forever at `radius` but not at `radius - 1`, i.e. whether `radius` is
+ * on the frontier of what this decider can do for it. At radius 1, this is just whether it loops.
+ */
+pub fn decided_exactly_at(program: &Program, radius: u8, max_context_count: usize) -> bool {
+    decided_exactly_at_with_config(
+        program,
+        radius,
+        &ClassifyConfig::new(radius).with_max_context_count(max_context_count),
+    )
+}
+
+/**
+ * Like `decided_exactly_at`, with every setting but the radius taken from `config`.
+ * A trivial loop, or a machine halting in presimulation, is settled once before either radius is
+ * tried, since neither depends on the radius. The reachable contexts are not carried over,
+ * though: a closed set at one radius does not project onto a closed set at the other (see
+ * `classify_multi`), so the smaller radius is only tried once the larger one decides the
+ * machine, and starts from scratch.
+ */
+pub fn decided_exactly_at_with_config(
+    program: &Program,
+    radius: u8,
+    config: &ClassifyConfig,
+) -> bool {
+    if is_trivial_loop(program) {
+        return radius <= Radius::MIN;
+    }
+    if let Some(presim_steps) = config.presim_steps {
+        if steps_to_halt(program, presim_steps).is_some() {
+            return false;
+        }
+    }
+    // With a cap, a radius would also count as deciding whatever a smaller one decides.
+    let config = ClassifyConfig {
+        presim_steps: None,
+        auto_cap_radius: false,
+        ..config.clone()
+    };
+    classify_with_config(program, &config.clone().with_radius(radius)).is_ok()
+        && (radius <= Radius::MIN
+            || classify_with_config(program, &config.with_radius(radius - 1)).is_err())
+}
+
+/**
+ * Parses and classifies each of `machines` with `config`, in parallel, returning the results in
+ * the same order. A machine which does not parse (leniently, see `ParseMode::Lenient`) gets its
+ * `ParseError` in place of an outcome, without affecting the others.
+ */
+pub fn classify_strings(
+    machines: &[impl AsRef<str> + Sync],
+    config: &ClassifyConfig,
+) -> Vec<Result<ClassifyOutcome, ParseError>> {
+    machines
+        .par_iter()
+        .map(|machine| {
+            let program = Program::try_from_string(machine.as_ref().trim(), ParseMode::Lenient)?;
+            Ok(classify_with_config(&program, config))
+        })
+        .collect()
 }
@@ -1,6 +1,15 @@
+use crate::simulate::Simulation;
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, PartialOrd, Ord)]
 pub struct State(pub u8);
 
+impl State {
+    /**
+     * The halting state. A transition into `HALT` is treated the same as an undefined transition.
+     */
+    pub const HALT: State = State(0);
+}
+
 impl std::fmt::Display for State {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -13,7 +22,7 @@ impl std::fmt::Display for State {
                 3 => "C",
                 4 => "D",
                 5 => "E",
-                _ => todo!(),
+                n => return write!(f, "S{n}"),
             }
         )
     }
@@ -37,6 +46,19 @@ pub enum Dir {
     Right,
 }
 
+impl std::fmt::Display for Dir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Dir::Left => 'L',
+                Dir::Right => 'R',
+            }
+        )
+    }
+}
+
 impl Dir {
     pub fn opposite(self) -> Self {
         match self {
@@ -73,92 +95,487 @@ impl BitBlock for Bit {
 #[derive(Debug)]
 pub struct Program<Sym: BitBlock = Bit> {
     pub by_input_array: Sym::FiveStorage, // [Option<(State, Bit, Dir)>; 10], // HashMap<(Bit, State), (State, Bit, Dir)>,
+    /**
+     * The machine uses states `1..=state_count`, and at most 5 of them.
+     * The rows of `by_input_array` for any states past these are always empty.
+     */
+    pub state_count: u8,
 }
 impl<Sym: BitBlock + Clone> Program<Sym> {
     pub fn action(&self, read: Sym, state: State) -> Result<(State, Sym, Dir), MayHalt> {
+        if state == State::HALT {
+            // There is no row for the halting state; being in it means we have already halted.
+            return Err(MayHalt::HaltingTransition);
+        }
         match Sym::get_by(read, (state.0 - 1) as usize, &self.by_input_array) {
-            None => Err(MayHalt),
+            None => Err(MayHalt::HaltingTransition),
+            Some((State::HALT, _, _)) => Err(MayHalt::HaltingTransition),
             Some(ans) => Ok(ans.clone()),
         }
     }
 }
 
+/**
+ * How `Program::to_string_in` lays out a machine's transitions.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum MachineFormat {
+    /**
+     * One `_`-separated group per state, like '1RB0LC_0LA1RD_1LA0RB_1LE---_0RA1RE'.
+     */
+    #[default]
+    Separated,
+    /**
+     * All groups run together, like '1RB0LC0LA1RD1LA0RB1LE---0RA1RE'.
+     */
+    Packed,
+}
+
+/**
+ * Why a machine string could not be parsed.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ParseError {
+    /**
+     * The string's length does not match any supported number of states.
+     */
+    UnknownFormat {
+        len: usize,
+    },
+    UnknownState(u8),
+    UnknownBit(u8),
+    UnknownDir(u8),
+    /**
+     * In `ParseMode::Strict`, the transition starting at byte `position` uses a halting marker
+     * other than the canonical one.
+     */
+    NonCanonicalHalt {
+        position: usize,
+    },
+}
+
+/**
+ * Which encodings of a machine `Program::try_from_string` accepts.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum ParseMode {
+    /**
+     * Any mix of text and raw seed database bytes, with surrounding whitespace ignored and
+     * letters in either case. A transition halts if its target state is `-`, `Z`, `H` or the
+     * byte 0, whatever its other two characters are.
+     */
+    #[default]
+    Lenient,
+    /**
+     * Either all uppercase text, where a halting transition must be exactly `---`, or all raw
+     * seed database bytes (detected from the first byte), where it must be exactly three 0 bytes.
+     * Anything else, including surrounding whitespace, is an error.
+     */
+    Strict,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnknownFormat { len } => write!(f, "unknown format (got {len} bytes), expected a string like '1RB0LC_0LA1RD_1LA0RB_1LE---_0RA1RE' or '1RB0LC0LA1RD1LA0RB1LE---0RA1RE', with 1 to 5 states"),
+            ParseError::UnknownState(c) => write!(f, "unknown color {}", c),
+            ParseError::UnknownBit(c) => write!(f, "unknown bit {}", c),
+            ParseError::UnknownDir(c) => write!(f, "unknown dir {}", c),
+            ParseError::NonCanonicalHalt { position } => write!(
+                f,
+                "non-canonical halting transition at byte {position}, expected '---' (or three 0 bytes in a raw record)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 impl Program {
+    /**
+     * Parses a machine, panicking if it is malformed. See `try_from_string`.
+     */
     pub fn from_string(s: &str) -> Program {
-        if s.len() == 34 || s.len() == 30 {
-            let s = s.as_bytes();
-            let mut rules: Program<Bit> = Program {
-                by_input_array: [None; 10],
+        Program::try_from_string(s, ParseMode::Lenient).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /**
+     * Parses a machine given as either a `_`-separated string like '1RB0LC_0LA1RD_1LA0RB_1LE---_0RA1RE'
+     * (`7 * n - 1` characters for `n` states) or a packed string like '1RB0LC0LA1RD1LA0RB1LE---0RA1RE'
+     * (`6 * n` characters), for 1 to 5 states. The raw 30-byte records of the seed database (where
+     * every character is replaced by its numeric value) are also accepted.
+     * `mode` decides which halting markers are allowed, and whether the two encodings may be mixed.
+     */
+    pub fn try_from_string(s: &str, mode: ParseMode) -> Result<Program, ParseError> {
+        Program::try_from_bytes(s.as_bytes(), mode)
+    }
+
+    /**
+     * Parses a packed 5-state machine straight from its 30 bytes, such as a raw seed database record.
+     */
+    pub fn from_bytes_30(bytes: &[u8; 30]) -> Result<Program, ParseError> {
+        Program::try_from_bytes(bytes, ParseMode::Lenient)
+    }
+
+    /**
+     * Parses a `_`-separated 5-state machine straight from its 34 bytes.
+     */
+    pub fn from_bytes_34(bytes: &[u8; 34]) -> Result<Program, ParseError> {
+        Program::try_from_bytes(bytes, ParseMode::Lenient)
+    }
+
+    /**
+     * Like `try_from_string`, but without requiring the input to be UTF-8 first.
+     */
+    pub fn try_from_bytes(s: &[u8], mode: ParseMode) -> Result<Program, ParseError> {
+        let lenient = mode == ParseMode::Lenient;
+        let s = if lenient { s.trim_ascii() } else { s };
+        // Raw bytes are all below b'A', so this leaves them alone.
+        let normalize = |c: u8| if lenient { c.to_ascii_uppercase() } else { c };
+        let format_error = ParseError::UnknownFormat { len: s.len() };
+
+        // Each state's group is 6 characters, plus a `_` between groups in the separated format.
+        let (state_count, stride) = if s.len().is_multiple_of(6) {
+            (s.len() / 6, 6)
+        } else if (s.len() + 1).is_multiple_of(7) {
+            ((s.len() + 1) / 7, 7)
+        } else {
+            return Err(format_error);
+        };
+        if !(1..=5).contains(&state_count) {
+            return Err(format_error);
+        }
+        if stride == 7 && (1..state_count).any(|group| s[group * 7 - 1] != b'_') {
+            return Err(format_error);
+        }
+        let state_count = state_count as u8;
+
+        let (allow_text, allow_raw) = match mode {
+            ParseMode::Lenient => (true, true),
+            // Raw records start with the bit 0 or 1 written by A0, where text has '0' or '1'.
+            ParseMode::Strict if s[0] <= 1 => (false, true),
+            ParseMode::Strict => (true, false),
+        };
+
+        let mut rules: Program<Bit> = Program {
+            by_input_array: [None; 10],
+            state_count,
+        };
+
+        let color_from_char = |c: u8| -> Result<State, ParseError> {
+            let state = match normalize(c) {
+                b'A' if allow_text => State(1),
+                b'B' if allow_text => State(2),
+                b'C' if allow_text => State(3),
+                b'D' if allow_text => State(4),
+                b'E' if allow_text => State(5),
+                1..=5 if allow_raw => State(c),
+                _ => return Err(ParseError::UnknownState(c)),
             };
+            if state.0 > state_count {
+                return Err(ParseError::UnknownState(c));
+            }
+            Ok(state)
+        };
+        let bit_from_char = |c: u8| -> Result<Bit, ParseError> {
+            match c {
+                b'0' if allow_text => Ok(Bit(false)),
+                b'1' if allow_text => Ok(Bit(true)),
+                0 if allow_raw => Ok(Bit(false)),
+                1 if allow_raw => Ok(Bit(true)),
+                _ => Err(ParseError::UnknownBit(c)),
+            }
+        };
+        let dir_from_char = |c: u8| -> Result<Dir, ParseError> {
+            match normalize(c) {
+                b'R' if allow_text => Ok(Dir::Right),
+                b'L' if allow_text => Ok(Dir::Left),
+                0 if allow_raw => Ok(Dir::Right),
+                1 if allow_raw => Ok(Dir::Left),
+                _ => Err(ParseError::UnknownDir(c)),
+            }
+        };
 
-            fn color_from_char(c: u8) -> State {
-                if c == b'A' || c == 1 {
-                    return State(1);
-                }
-                if c == b'B' || c == 2 {
-                    return State(2);
-                }
-                if c == b'C' || c == 3 {
-                    return State(3);
-                }
-                if c == b'D' || c == 4 {
-                    return State(4);
-                }
-                if c == b'E' || c == 5 {
-                    return State(5);
+        for color in [
+            (State(1), 0),
+            (State(2), 1),
+            (State(3), 2),
+            (State(4), 3),
+            (State(5), 4),
+        ]
+        .into_iter()
+        .take(state_count as usize)
+        {
+            for bit in [(Bit(false), 0), (Bit(true), 1)] {
+                let i = color.1 * stride + bit.1 * 3;
+
+                let target = normalize(s[i + 2]);
+                if target == b'-' || target == 0 || target == b'Z' || target == b'H' {
+                    // Halting state.
+                    let canonical = match (allow_text, allow_raw) {
+                        (true, true) => true,
+                        (true, false) => &s[i..i + 3] == b"---",
+                        (false, _) => s[i..i + 3] == [0, 0, 0],
+                    };
+                    if !canonical {
+                        return Err(ParseError::NonCanonicalHalt { position: i });
+                    }
+                    continue;
                 }
-                panic!("unknown color {}", c);
+
+                let conc = (
+                    color_from_char(s[i + 2])?,
+                    bit_from_char(s[i])?,
+                    dir_from_char(s[i + 1])?,
+                );
+
+                *Bit::get_by_mut(bit.0, color.1, &mut rules.by_input_array) = Some(conc);
             }
-            fn bit_from_char(c: u8) -> Bit {
-                if c == b'0' || c == 0 {
-                    return Bit(false);
-                }
-                if c == b'1' || c == 1 {
-                    return Bit(true);
-                }
-                panic!("unknown bit {}", c);
+        }
+
+        Ok(rules)
+    }
+}
+
+impl Program {
+    /**
+     * A machine with `state_count` states, from 1 to 5, none of whose transitions are set yet,
+     * so that every one of them halts. Set them with `with_transition`, e.g. to enumerate
+     * machines without going through text.
+     */
+    pub fn new(state_count: u8) -> Program {
+        assert!(
+            (1..=5).contains(&state_count),
+            "a machine has 1 to 5 states, not {state_count}"
+        );
+        Program {
+            by_input_array: [None; 10],
+            state_count,
+        }
+    }
+
+    /**
+     * The machine with the transition for reading `read` in `state` set to `action`: write
+     * `action.1`, move `action.2` and go to `action.0`, where `State::HALT` halts. Panics if
+     * either state is not one of the machine's own.
+     */
+    pub fn with_transition(
+        mut self,
+        state: State,
+        read: Bit,
+        action: (State, Bit, Dir),
+    ) -> Program {
+        let is_own = |state: State| (1..=self.state_count).contains(&state.0);
+        assert!(is_own(state), "{state} is not a state of the machine");
+        assert!(
+            action.0 == State::HALT || is_own(action.0),
+            "{} is not a state of the machine",
+            action.0
+        );
+        *read.get_by_mut((state.0 - 1) as usize, &mut self.by_input_array) = Some(action);
+        self
+    }
+
+    /**
+     * Writes the machine back out as text, with every halting transition as '---'.
+     * Parsing the result gives back the same machine.
+     */
+    pub fn to_string_in(&self, format: MachineFormat) -> String {
+        let mut out = String::new();
+        for state in 0..self.state_count as usize {
+            if state > 0 && format == MachineFormat::Separated {
+                out.push('_');
             }
-            fn dir_from_char(c: u8) -> Dir {
-                if c == b'R' || c == 0 {
-                    return Dir::Right;
-                }
-                if c == b'L' || c == 1 {
-                    return Dir::Left;
+            for bit in [Bit(false), Bit(true)] {
+                match Bit::get_by(bit, state, &self.by_input_array) {
+                    None | Some((State::HALT, _, _)) => out.push_str("---"),
+                    Some((next, write, dir)) => out.push_str(&format!("{write}{dir}{next}")),
                 }
-                panic!("unknown dir {}", c);
             }
+        }
+        out
+    }
 
-            for color in [
-                (State(1), 0),
-                (State(2), 1),
-                (State(3), 2),
-                (State(4), 3),
-                (State(5), 4),
-            ] {
-                for bit in [(Bit(false), 0), (Bit(true), 1)] {
-                    let i = color.1 * (if s.len() == 34 { 7 } else { 6 }) + bit.1 * 3;
-
-                    if s[i + 2] == b'-' || s[i + 2] == 0 || s[i + 2] == b'Z' || s[i + 2] == b'H' {
-                        // Halting state.
-                        continue;
-                    }
+    /**
+     * The machine's mirror image, with every `Dir::Left` turned into `Dir::Right` and vice versa.
+     * It runs exactly like the machine on a left-right flipped tape, so it loops forever if and
+     * only if the machine does.
+     */
+    pub fn mirror(&self) -> Program {
+        let mut by_input_array: [Option<(State, Bit, Dir)>; 10] = self.by_input_array;
+        for (_, _, dir) in by_input_array.iter_mut().flatten() {
+            *dir = dir.opposite();
+        }
+        Program {
+            by_input_array,
+            state_count: self.state_count,
+        }
+    }
 
-                    let conc = (
-                        color_from_char(s[i + 2]),
-                        bit_from_char(s[i]),
-                        dir_from_char(s[i + 1]),
-                    );
+    /**
+     * The machine with the meaning of 0 and 1 swapped, both in what it reads and in what it
+     * writes. It runs on a tape with every cell inverted exactly like the machine does, so over
+     * a tape of blank 1s, the machine behaves like its inverse over blank 0s.
+     */
+    pub fn invert_bits(&self) -> Program {
+        let mut by_input_array: [Option<(State, Bit, Dir)>; 10] = self.by_input_array;
+        for row in by_input_array.chunks_exact_mut(2) {
+            row.swap(0, 1);
+        }
+        for (_, bit, _) in by_input_array.iter_mut().flatten() {
+            *bit = Bit(!bit.0);
+        }
+        Program {
+            by_input_array,
+            state_count: self.state_count,
+        }
+    }
 
-                    *Bit::get_by_mut(bit.0, color.1, &mut rules.by_input_array) = Some(conc);
-                }
+    /**
+     * The machine with every transition out of `states` removed, so that entering one of them
+     * halts, as if the machine had only its other states.
+     */
+    pub fn with_halting_states(&self, states: &[State]) -> Program {
+        let mut by_input_array: [Option<(State, Bit, Dir)>; 10] = self.by_input_array;
+        for state in states.iter().filter(|state| (1..=5).contains(&state.0)) {
+            for bit in [Bit(false), Bit(true)] {
+                *bit.get_by_mut((state.0 - 1) as usize, &mut by_input_array) = None;
+            }
+        }
+        Program {
+            by_input_array,
+            state_count: self.state_count,
+        }
+    }
+
+    /**
+     * The machine in tree normal form: states are renamed in the order the machine first enters
+     * them when run from the blank tape, and every direction is flipped if A0 moves left, so
+     * that machines differing only in those choices are written the same way.
+     * The run stops after `max_steps` steps; states not entered by then keep their relative order.
+     */
+    pub fn to_tnf(&self, max_steps: u64) -> Program {
+        let mut order = vec![State(1)];
+        let mut simulation = Simulation::new(self);
+        while order.len() < self.state_count as usize
+            && simulation.steps() < max_steps
+            && simulation.step()
+        {
+            if !order.contains(&simulation.state()) {
+                order.push(simulation.state());
             }
+        }
+        let unvisited: Vec<State> = (1..=self.state_count)
+            .map(State)
+            .filter(|state| !order.contains(state))
+            .collect();
+        order.extend(unvisited);
 
-            return rules;
+        let mut renamed = [State::HALT; 6];
+        for (new, old) in order.iter().enumerate() {
+            renamed[old.0 as usize] = State(new as u8 + 1);
+        }
+        let mirror = matches!(self.action(Bit(false), State(1)), Ok((_, _, Dir::Left)));
+
+        let mut tnf = Program {
+            by_input_array: [None; 10],
+            state_count: self.state_count,
+        };
+        for (new, old) in order.iter().enumerate() {
+            for bit in [Bit(false), Bit(true)] {
+                if let Some((next, write, dir)) =
+                    *Bit::get_by(bit, (old.0 - 1) as usize, &self.by_input_array)
+                {
+                    let dir = if mirror { dir.opposite() } else { dir };
+                    *Bit::get_by_mut(bit, new, &mut tnf.by_input_array) =
+                        Some((renamed[next.0 as usize], write, dir));
+                }
+            }
         }
+        tnf
+    }
+}
 
-        panic!("unknown format, expected a 34-character string like '1RB0LC_0LA1RD_1LA0RB_1LE---_0RA1RE' or a 30-character string like '1RB0LC0LA1RD1LA0RB1LE---0RA1RE'");
+impl std::fmt::Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_string_in(MachineFormat::Separated))
     }
 }
 
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct LoopsForever;
-pub struct MayHalt;
+/**
+ * Why a machine could not be shown to loop forever.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MayHalt {
+    /**
+     * A halting transition was reached. For a classification, this means some reachable local
+     * context halts, so a larger radius (rather than a larger budget) is needed, if anything helps.
+     */
+    HaltingTransition,
+    /**
+     * The reachable set was still growing when it hit the context budget.
+     */
+    ContextBudget,
+    /**
+     * The reachable set was still growing when it hit the memory cap.
+     */
+    MemoryBudget,
+    /**
+     * The reachable set was still growing when the timeout ran out.
+     */
+    Timeout,
+    /**
+     * The machine was seen to halt after `steps` steps while simulating it before classification.
+     */
+    HaltedInPresim { steps: u64 },
+    /**
+     * The start transition, state A reading the blank symbol, is undefined (or goes to the
+     * halting state), so the machine halts on its very first step without ever moving. This is
+     * a halt, never a loop, but is kept apart from `HaltingTransition` since it usually means a
+     * malformed or partially specified machine rather than one worth a larger radius.
+     */
+    StuckAtStart,
+    /**
+     * Saturation finished, but the reachable set is still not closed under a program step.
+     */
+    NotClosed,
+}
+
+impl MayHalt {
+    /**
+     * Whether classification gave up because it ran out of budget (contexts, memory or time),
+     * so that a larger budget at the same radius might still decide the machine. Otherwise,
+     * only a different radius can help.
+     */
+    pub fn is_budget_limited(self) -> bool {
+        match self {
+            MayHalt::ContextBudget | MayHalt::MemoryBudget | MayHalt::Timeout => true,
+            MayHalt::HaltingTransition
+            | MayHalt::HaltedInPresim { .. }
+            | MayHalt::StuckAtStart
+            | MayHalt::NotClosed => false,
+        }
+    }
+}
+
+impl std::fmt::Display for MayHalt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                MayHalt::HaltingTransition => "a reachable context halts",
+                MayHalt::ContextBudget => "context budget exhausted",
+                MayHalt::MemoryBudget => "memory cap reached",
+                MayHalt::Timeout => "timed out",
+                MayHalt::HaltedInPresim { steps } => {
+                    return write!(f, "halted after {steps} steps during presimulation");
+                }
+                MayHalt::StuckAtStart => "the start transition halts",
+                MayHalt::NotClosed => "not closed",
+            }
+        )
+    }
+}
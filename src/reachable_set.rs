@@ -0,0 +1,162 @@
+use crate::ngram::{LocalContext, Radius};
+
+/**
+ * Storage for a set of reachable local contexts, all at the same radius.
+ * Iteration is always in ascending order, whatever the backend, so that nothing a run writes
+ * depends on which one was compiled in.
+ */
+pub(crate) trait ReachableSet {
+    fn new(radius: Radius) -> Self;
+    /**
+     * Adds `context`. Returns true if it was not already in the set.
+     */
+    fn insert(&mut self, context: LocalContext) -> bool;
+    fn contains(&self, context: &LocalContext) -> bool;
+    fn len(&self) -> usize;
+    fn iter(&self) -> impl Iterator<Item = LocalContext> + '_;
+}
+
+/**
+ * The backend selected by the `backend-*` features: `backend-hash`, then `backend-bitset`,
+ * and otherwise (by default) `backend-btree`.
+ */
+#[cfg(feature = "backend-hash")]
+pub(crate) type ContextSet = HashContextSet;
+#[cfg(all(feature = "backend-bitset", not(feature = "backend-hash")))]
+pub(crate) type ContextSet = BitsetContextSet;
+#[cfg(not(any(feature = "backend-hash", feature = "backend-bitset")))]
+pub(crate) type ContextSet = BTreeContextSet;
+
+/**
+ * A `BTreeSet`: deterministic and compact, with logarithmic lookups.
+ */
+#[cfg(not(any(feature = "backend-hash", feature = "backend-bitset")))]
+pub(crate) struct BTreeContextSet(std::collections::BTreeSet<LocalContext>);
+
+#[cfg(not(any(feature = "backend-hash", feature = "backend-bitset")))]
+impl ReachableSet for BTreeContextSet {
+    fn new(_radius: Radius) -> Self {
+        BTreeContextSet(std::collections::BTreeSet::new())
+    }
+    fn insert(&mut self, context: LocalContext) -> bool {
+        self.0.insert(context)
+    }
+    fn contains(&self, context: &LocalContext) -> bool {
+        self.0.contains(context)
+    }
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+    fn iter(&self) -> impl Iterator<Item = LocalContext> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+/**
+ * A hash set with the Fx hasher: constant-time lookups, paid for by sorting on every iteration.
+ */
+#[cfg(feature = "backend-hash")]
+pub(crate) struct HashContextSet(rustc_hash::FxHashSet<LocalContext>);
+
+#[cfg(feature = "backend-hash")]
+impl ReachableSet for HashContextSet {
+    fn new(_radius: Radius) -> Self {
+        HashContextSet(rustc_hash::FxHashSet::default())
+    }
+    fn insert(&mut self, context: LocalContext) -> bool {
+        self.0.insert(context)
+    }
+    fn contains(&self, context: &LocalContext) -> bool {
+        self.0.contains(context)
+    }
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+    fn iter(&self) -> impl Iterator<Item = LocalContext> + '_ {
+        let mut contexts: Vec<LocalContext> = self.0.iter().copied().collect();
+        contexts.sort_unstable();
+        contexts.into_iter()
+    }
+}
+
+/**
+ * The most bits `BitsetContextSet` gives its bitset, 512 KiB worth. That covers every context
+ * while `left + right` is at most 18, e.g. radius 9 on both sides; past it, every context goes
+ * to the fallback set instead.
+ */
+#[cfg(all(feature = "backend-bitset", not(feature = "backend-hash")))]
+const BITSET_MAX_BITS: usize = 1 << 22;
+
+/**
+ * One bit per possible context, indexed by the state and the window bits together, with the
+ * bitset sized from the radius up front. It is fastest at small radii, where the whole bitset
+ * stays small; at a radius where it would take more than `BITSET_MAX_BITS`, it is left empty
+ * and every context is kept in a `BTreeSet` instead.
+ */
+#[cfg(all(feature = "backend-bitset", not(feature = "backend-hash")))]
+pub(crate) struct BitsetContextSet {
+    width: u8,
+    bits: bitvec::vec::BitVec,
+    overflow: std::collections::BTreeSet<LocalContext>,
+    len: usize,
+}
+
+#[cfg(all(feature = "backend-bitset", not(feature = "backend-hash")))]
+impl BitsetContextSet {
+    /**
+     * The bit of `context`, if the bitset is in use. This follows the order of contexts, which
+     * compare by state first and then by window bits.
+     */
+    fn index(&self, context: &LocalContext) -> Option<usize> {
+        let index = usize::from(context.state.0) << self.width | context.nearby_bits as usize;
+        (index < self.bits.len()).then_some(index)
+    }
+}
+
+#[cfg(all(feature = "backend-bitset", not(feature = "backend-hash")))]
+impl ReachableSet for BitsetContextSet {
+    fn new(radius: Radius) -> Self {
+        let width = radius.width();
+        // A bit for each state from 0 to 5 and each window.
+        let bitset_bits = (width < 22)
+            .then(|| 6 << width)
+            .filter(|&bits| bits <= BITSET_MAX_BITS)
+            .unwrap_or(0);
+        BitsetContextSet {
+            width,
+            bits: bitvec::vec::BitVec::repeat(false, bitset_bits),
+            overflow: std::collections::BTreeSet::new(),
+            len: 0,
+        }
+    }
+    fn insert(&mut self, context: LocalContext) -> bool {
+        let inserted = match self.index(&context) {
+            Some(index) => !self.bits.replace(index, true),
+            None => self.overflow.insert(context),
+        };
+        self.len += usize::from(inserted);
+        inserted
+    }
+    fn contains(&self, context: &LocalContext) -> bool {
+        match self.index(context) {
+            Some(index) => self.bits[index],
+            None => self.overflow.contains(context),
+        }
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn iter(&self) -> impl Iterator<Item = LocalContext> + '_ {
+        let window = (1u64 << self.width) - 1;
+        self.bits
+            .iter_ones()
+            .map(move |index| {
+                let index = index as u64;
+                LocalContext::new(
+                    crate::program::State((index >> self.width) as u8),
+                    index & window,
+                )
+            })
+            .chain(self.overflow.iter().copied())
+    }
+}
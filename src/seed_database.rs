@@ -0,0 +1,214 @@
+use std::io::{self, Read, Seek};
+use std::path::Path;
+
+use crate::program::{ParseError, ParseMode, Program};
+
+/**
+ * Each machine in the seed database is stored as a 30-byte record, after one 30-byte header record.
+ */
+pub const RECORD_SIZE: u64 = 30;
+
+/**
+ * The seed database's header record. Its first three fields are 4-byte big-endian counts, the
+ * third being the number of machine records that follow it; the rest of the header is unused here.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct SeedDatabaseHeader {
+    /**
+     * Machines that were still running when the seed enumeration's step limit was reached.
+     */
+    pub time_limit_count: u32,
+    /**
+     * Machines that had used too many cells when the seed enumeration gave up on them.
+     */
+    pub space_limit_count: u32,
+    pub machine_count: u32,
+}
+
+impl SeedDatabaseHeader {
+    fn from_bytes(bytes: &[u8; RECORD_SIZE as usize]) -> Self {
+        let field = |i: usize| u32::from_be_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+        SeedDatabaseHeader {
+            time_limit_count: field(0),
+            space_limit_count: field(1),
+            machine_count: field(2),
+        }
+    }
+}
+
+/**
+ * Why the seed database could not be opened, or a machine could not be read from it.
+ */
+#[derive(Debug)]
+pub enum SeedDatabaseError {
+    Io(io::Error),
+    /**
+     * The file's length is not a whole number of records, or it does not even hold the header.
+     */
+    BadLength {
+        len: u64,
+    },
+    /**
+     * The header's machine count disagrees with the number of records in the file.
+     */
+    HeaderMismatch {
+        header_count: u32,
+        record_count: u64,
+    },
+    /**
+     * A machine index past the last record.
+     */
+    OutOfRange {
+        index: u32,
+        machine_count: u32,
+    },
+    /**
+     * The record at `index` is not a machine that the parse mode accepts.
+     */
+    Parse {
+        index: u32,
+        err: ParseError,
+    },
+}
+
+impl std::fmt::Display for SeedDatabaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SeedDatabaseError::Io(err) => write!(f, "{err}"),
+            SeedDatabaseError::BadLength { len } => write!(
+                f,
+                "{len} bytes long, which is not a {RECORD_SIZE}-byte header followed by whole {RECORD_SIZE}-byte records"
+            ),
+            SeedDatabaseError::HeaderMismatch {
+                header_count,
+                record_count,
+            } => write!(
+                f,
+                "the header says it holds {header_count} machines, but the file holds {record_count} records"
+            ),
+            SeedDatabaseError::OutOfRange {
+                index,
+                machine_count,
+            } => write!(
+                f,
+                "machine index {index} is out of range, since only {machine_count} machines are held (indices 0..{machine_count})"
+            ),
+            SeedDatabaseError::Parse { index, err } => write!(f, "machine index {index}: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SeedDatabaseError {}
+
+impl From<io::Error> for SeedDatabaseError {
+    fn from(err: io::Error) -> Self {
+        SeedDatabaseError::Io(err)
+    }
+}
+
+/**
+ * Random access to the records of the seed database, either through a memory map (no syscalls
+ * per record) or through plain `seek` + `read` calls.
+ */
+enum SeedRecords {
+    Mapped(memmap2::Mmap),
+    Unmapped(std::fs::File),
+}
+
+impl SeedRecords {
+    /**
+     * Reads the `record`-th record, counting the header as record 0.
+     */
+    fn read(&mut self, record: u64) -> io::Result<[u8; RECORD_SIZE as usize]> {
+        let offset = record * RECORD_SIZE;
+        let mut bytes = [0; RECORD_SIZE as usize];
+        match self {
+            SeedRecords::Mapped(map) => {
+                let offset = offset as usize;
+                bytes.copy_from_slice(&map[offset..offset + RECORD_SIZE as usize]);
+            }
+            SeedRecords::Unmapped(file) => {
+                file.seek(io::SeekFrom::Start(offset))?;
+                file.read_exact(&mut bytes)?;
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+/**
+ * A bbchallenge seed database, where machine `i` is the record at byte `(i + 1) * RECORD_SIZE`.
+ */
+pub struct SeedDatabase {
+    records: SeedRecords,
+    header: SeedDatabaseHeader,
+}
+
+impl SeedDatabase {
+    /**
+     * Opens the database at `path` and checks that its length and header agree with each other.
+     */
+    pub fn open(path: impl AsRef<Path>, use_mmap: bool) -> Result<Self, SeedDatabaseError> {
+        let file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        if len < RECORD_SIZE || !len.is_multiple_of(RECORD_SIZE) {
+            return Err(SeedDatabaseError::BadLength { len });
+        }
+        let mut records = if use_mmap {
+            // SAFETY: the seed database is only ever read, and it is not expected to be modified
+            // while a run is using it.
+            SeedRecords::Mapped(unsafe { memmap2::Mmap::map(&file) }?)
+        } else {
+            SeedRecords::Unmapped(file)
+        };
+        let header = SeedDatabaseHeader::from_bytes(&records.read(0)?);
+
+        let record_count = len / RECORD_SIZE - 1;
+        if u64::from(header.machine_count) != record_count {
+            return Err(SeedDatabaseError::HeaderMismatch {
+                header_count: header.machine_count,
+                record_count,
+            });
+        }
+        Ok(SeedDatabase { records, header })
+    }
+
+    pub fn header(&self) -> SeedDatabaseHeader {
+        self.header
+    }
+
+    /**
+     * The number of machines in the database.
+     */
+    pub fn len(&self) -> u32 {
+        self.header.machine_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /**
+     * The raw bytes of machine `index`.
+     */
+    pub fn get_bytes(
+        &mut self,
+        index: u32,
+    ) -> Result<[u8; RECORD_SIZE as usize], SeedDatabaseError> {
+        if index >= self.len() {
+            return Err(SeedDatabaseError::OutOfRange {
+                index,
+                machine_count: self.len(),
+            });
+        }
+        Ok(self.records.read(u64::from(index) + 1)?)
+    }
+
+    /**
+     * Machine `index`, parsed according to `mode`.
+     */
+    pub fn get(&mut self, index: u32, mode: ParseMode) -> Result<Program, SeedDatabaseError> {
+        let bytes = self.get_bytes(index)?;
+        Program::try_from_bytes(&bytes, mode).map_err(|err| SeedDatabaseError::Parse { index, err })
+    }
+}
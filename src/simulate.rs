@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+
+use crate::program::{Bit, Dir, Program, State};
+
+/**
+ * How a bounded simulation from the blank tape ended.
+ */
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SimulationResult {
+    /**
+     * The machine reached an undefined (halting) transition, which was its `steps`-th step: as is
+     * usual for busy beavers, the halting transition counts as a step.
+     */
+    Halted { steps: u64 },
+    /**
+     * The machine was still running when the step limit was reached.
+     */
+    StillRunning,
+}
+
+/**
+ * A machine running on a concrete, unbounded tape which starts out blank.
+ */
+pub struct Simulation<'a> {
+    program: &'a Program,
+    tape: VecDeque<Bit>,
+    head: usize,
+    state: State,
+    steps: u64,
+}
+
+impl<'a> Simulation<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        Simulation {
+            program,
+            tape: [Bit(false)].into_iter().collect(),
+            head: 0,
+            state: State(1),
+            steps: 0,
+        }
+    }
+
+    /**
+     * Takes one step. Returns false, without changing anything, if the machine has halted.
+     */
+    pub fn step(&mut self) -> bool {
+        let (state, bit, dir) = match self.program.action(self.tape[self.head], self.state) {
+            Ok(action) => action,
+            Err(_) => return false,
+        };
+        self.tape[self.head] = bit;
+        self.state = state;
+        match dir {
+            Dir::Left => {
+                if self.head == 0 {
+                    self.tape.push_front(Bit(false));
+                } else {
+                    self.head -= 1;
+                }
+            }
+            Dir::Right => {
+                self.head += 1;
+                if self.head == self.tape.len() {
+                    self.tape.push_back(Bit(false));
+                }
+            }
+        }
+        self.steps += 1;
+        true
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /**
+     * The cells visited so far, from leftmost to rightmost. Every other cell is blank.
+     */
+    pub fn tape(&self) -> &VecDeque<Bit> {
+        &self.tape
+    }
+
+    /**
+     * The head's position, as an index into `tape`.
+     */
+    pub fn head(&self) -> usize {
+        self.head
+    }
+}
+
+/**
+ * Runs `program` from the blank tape for at most `max_steps` steps.
+ */
+pub fn simulate(program: &Program, max_steps: u64) -> SimulationResult {
+    let mut simulation = Simulation::new(program);
+    while simulation.steps() < max_steps {
+        if !simulation.step() {
+            // Count the halting transition itself.
+            return SimulationResult::Halted {
+                steps: simulation.steps() + 1,
+            };
+        }
+    }
+    SimulationResult::StillRunning
+}
+
+/**
+ * The number of steps `program` takes from the blank tape to halt, the halting transition
+ * included, or `None` if it has not halted within `max_steps` steps, which are then a lower
+ * bound.
+ */
+pub fn steps_to_halt(program: &Program, max_steps: u64) -> Option<u64> {
+    match simulate(program, max_steps) {
+        SimulationResult::Halted { steps } => Some(steps),
+        SimulationResult::StillRunning => None,
+    }
+}